@@ -0,0 +1,725 @@
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::CharIndices;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::Category;
+
+/// The largest number of blank lines accepted anywhere in a configuration.
+const MAX_BLANK_LINES: usize = 3;
+
+/// Settings that control how items are reordered.
+///
+/// The default configuration reproduces the built-in ordering. A configuration
+/// can be loaded from a `reorder.toml` file such as:
+///
+/// ```toml
+/// category_order = ["pub_use", "use", "mod", "type", "const", "typedef", "impl", "trait", "fn", "tests"]
+/// ```
+///
+/// Categories left out of `category_order` keep their default relative order
+/// and are placed after the listed ones.
+///
+/// Blank-line spacing can be adjusted as well, either for every category at
+/// once or per category:
+///
+/// ```toml
+/// blank_lines_between = 2
+/// blank_lines_within = { fn = 0, impl = 2 }
+/// ```
+///
+/// Library users can set every field directly instead:
+///
+/// ```
+/// use cargo_refmt::{Category, Config};
+///
+/// let config = Config {
+///     blank_lines_within: [(Category::Fn, 0)].into(),
+///     impls_with_types: true,
+///     ..Config::default()
+/// };
+/// let src = "fn b() {}\nimpl Foo {}\nfn a() {}\nstruct Foo;\n";
+/// let out = cargo_refmt::reorder_source_with(src, &config).unwrap();
+/// assert_eq!(out, "struct Foo;\n\nimpl Foo {}\n\nfn a() {}\nfn b() {}\n");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Blank lines emitted between two categories.
+    pub blank_lines_between: usize,
+    /// Blank lines emitted between the items of a category, overriding the
+    /// built-in spacing for the categories present.
+    pub blank_lines_within: HashMap<Category, usize>,
+    /// Output order of categories.
+    pub category_order: Vec<Category>,
+    /// Collapse runs of blank lines among the comments and attributes in
+    /// front of an item to a single blank line.
+    pub collapse_blank_lines: bool,
+    /// Edition the sources are written for.
+    pub edition: Edition,
+    /// Whether the output ends with a newline, or `None` to keep the ending of
+    /// the input.
+    pub final_newline: Option<bool>,
+    /// Experimental: reorder the leading items of a file that does not parse,
+    /// up to the item that fails, and keep everything from there verbatim.
+    pub force_partial: bool,
+    /// Marker that identifies generated files, which are left untouched. An
+    /// empty marker disables the check.
+    pub generated_marker: String,
+    /// Emit `impl` blocks directly after the local type they implement.
+    pub impls_with_types: bool,
+    /// Line ending of the output, or `None` to use the dominant one of the
+    /// input.
+    pub line_ending: Option<LineEnding>,
+    /// Categories to reorder. Items of other categories keep their position,
+    /// with the reordered items flowing around them; empty means every category.
+    pub only: Vec<Category>,
+    /// Keep runs of adjacent items gated by the same `#[cfg(...)]` together,
+    /// in source order, at the position of the first item of the run.
+    pub preserve_cfg_groups: bool,
+    /// Keep the items between `// region` and `// endregion` markers together,
+    /// in source order, at the position of the first item of the region.
+    pub preserve_regions: bool,
+    /// Keep runs of `use` declarations separated by blank lines apart instead
+    /// of regrouping them by origin.
+    pub preserve_use_groups: bool,
+    /// Move `pub` and `pub(crate)` items ahead of private ones within each category.
+    pub pub_first: bool,
+    /// Sort the named fields of structs and the variants of enums by name,
+    /// except where their order is significant.
+    pub sort_fields: bool,
+    /// Sort the items within each impl block: associated constants and types,
+    /// then constructors, then other functions, by visibility and name.
+    pub sort_impl_items: bool,
+    /// Sort `use` declarations by path within each import group, ignoring
+    /// visibility and attributes.
+    pub sort_imports: bool,
+    /// Sort items within each category alphabetically by identifier.
+    pub sort_within: bool,
+    /// Keep every item in place and only space the items as if they had been
+    /// reordered, with the blank lines between categories where they change.
+    pub spacing_only: bool,
+    /// Remove a `#![reorder(...)]` attribute from the files it reorders
+    /// instead of keeping it for the next run.
+    pub strip_reorder_attribute: bool,
+    /// Remove blank lines at the start of the output and extra newlines at
+    /// its end, which the reordering itself leaves in files without items.
+    pub trim_blank_lines: bool,
+    /// Place type aliases in the `const` section, sorted by name together
+    /// with the constants and statics.
+    pub types_with_consts: bool,
+}
+
+/// The Rust edition sources are written for.
+///
+/// Only the 2015 edition changes the output: `extern crate` declarations are
+/// how crates are brought into scope there, so they always lead the file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Edition {
+    E2015,
+    E2018,
+    E2021,
+    #[default]
+    E2024,
+}
+
+/// A line ending style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Crlf,
+    Lf,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Array(Vec<Value>),
+    Bool(bool),
+    Integer(i64),
+    String(String),
+    Table(Vec<(String, Value)>),
+}
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    text: &'a str,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            blank_lines_between: 1,
+            blank_lines_within: HashMap::new(),
+            category_order: Category::DEFAULT_ORDER.to_vec(),
+            collapse_blank_lines: false,
+            edition: Edition::default(),
+            final_newline: None,
+            force_partial: false,
+            generated_marker: "@generated".to_string(),
+            impls_with_types: false,
+            line_ending: None,
+            only: Vec::new(),
+            preserve_cfg_groups: false,
+            preserve_regions: false,
+            preserve_use_groups: false,
+            pub_first: false,
+            sort_fields: false,
+            sort_impl_items: false,
+            sort_imports: false,
+            sort_within: false,
+            spacing_only: false,
+            strip_reorder_attribute: false,
+            trim_blank_lines: false,
+            types_with_consts: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parses a configuration from the contents of a `reorder.toml` file.
+    pub fn from_toml(text: &str) -> Result<Config> {
+        let mut config = Config::default();
+        let mut seen_keys = Vec::new();
+
+        for (line, key, value) in Parser::new(text).document()? {
+            if seen_keys.contains(&key) {
+                bail!("line {line}: duplicate key `{key}`");
+            }
+            match key.as_str() {
+                "blank_lines_between" => {
+                    config.blank_lines_between = parse_blank_lines(value)
+                        .with_context(|| format!("line {line}: invalid `blank_lines_between`"))?;
+                }
+                "blank_lines_within" => {
+                    config.blank_lines_within = parse_blank_lines_within(value)
+                        .with_context(|| format!("line {line}: invalid `blank_lines_within`"))?;
+                }
+                "category_order" => {
+                    config.category_order = parse_category_order(value)
+                        .with_context(|| format!("line {line}: invalid `category_order`"))?;
+                }
+                "generated_marker" => {
+                    let Value::String(marker) = value else {
+                        bail!("line {line}: invalid `generated_marker`: expected a string");
+                    };
+                    config.generated_marker = marker;
+                }
+                _ => bail!("line {line}: unknown key `{key}`"),
+            }
+            seen_keys.push(key);
+        }
+
+        Ok(config)
+    }
+
+    /// Reads and parses a configuration file.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("read config {}", path.display()))?;
+        Config::from_toml(&text).with_context(|| format!("parse config {}", path.display()))
+    }
+
+    pub(crate) fn blank_lines_within(&self, category: Category) -> usize {
+        self.blank_lines_within
+            .get(&category)
+            .copied()
+            .unwrap_or_else(|| category.blank_lines_within())
+    }
+
+    pub(crate) fn resolved_order(&self) -> Vec<Category> {
+        let mut order: Vec<Category> = Vec::new();
+        if self.edition == Edition::E2015 {
+            order.push(Category::ExternCrate);
+        }
+        for &category in self
+            .category_order
+            .iter()
+            .chain(Category::DEFAULT_ORDER.iter())
+        {
+            if order.contains(&category) {
+                continue;
+            }
+            // Foreign blocks, proc macros and unions used to share the `fn`
+            // and `typedef` sections, so orders written before they got their
+            // own keep them where they were.
+            let unlisted = |category| !self.category_order.contains(&category);
+            if category == Category::Fn {
+                for split in [Category::Foreign, Category::ProcMacro] {
+                    if unlisted(split) && !order.contains(&split) {
+                        order.push(split);
+                    }
+                }
+            }
+            order.push(category);
+            if category == Category::Typedef && unlisted(Category::Union) {
+                order.push(Category::Union);
+            }
+        }
+        order
+    }
+}
+
+impl std::str::FromStr for Edition {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            "2024" => Ok(Edition::E2024),
+            _ => bail!("unknown edition `{name}`; expected 2015, 2018, 2021 or 2024"),
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Parser {
+            chars: text.char_indices().peekable(),
+            line: 1,
+            text,
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                Some(']') => {
+                    self.next();
+                    return Ok(Value::Array(values));
+                }
+                Some(_) => values.push(self.value()?),
+                None => bail!("line {}: unterminated array", self.line),
+            }
+            self.skip_trivia();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => bail!("line {}: expected `,` or `]` in array", self.line),
+            }
+        }
+    }
+
+    fn document(mut self) -> Result<Vec<(usize, String, Value)>> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_trivia();
+            let Some(c) = self.peek() else {
+                return Ok(entries);
+            };
+            if c == '[' {
+                bail!("line {}: tables are not supported", self.line);
+            }
+
+            let line = self.line;
+            let key = self.word();
+            if key.is_empty() {
+                bail!("line {line}: expected a key");
+            }
+            self.skip_spaces();
+            if self.next() != Some('=') {
+                bail!("line {line}: expected `=` after `{key}`");
+            }
+            self.skip_spaces();
+            let value = self.value()?;
+
+            self.skip_spaces();
+            match self.peek() {
+                None | Some('\n') | Some('#') => {}
+                Some('\r') => {}
+                Some(_) => bail!("line {}: unexpected text after value", self.line),
+            }
+            entries.push((line, key, value));
+        }
+    }
+
+    fn table(&mut self) -> Result<Value> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some('}') && entries.is_empty() {
+                self.next();
+                return Ok(Value::Table(entries));
+            }
+            let key = self.word();
+            if key.is_empty() {
+                bail!("line {}: expected a key in inline table", self.line);
+            }
+            self.skip_spaces();
+            if self.next() != Some('=') {
+                bail!("line {}: expected `=` after `{key}`", self.line);
+            }
+            self.skip_spaces();
+            entries.push((key, self.value()?));
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Table(entries)),
+                _ => bail!("line {}: expected `,` or `}}` in inline table", self.line),
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                Some('#') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.next();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<Value> {
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(Value::String(value)),
+                Some('\\') if quote == '"' => match self.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ ('"' | '\\')) => value.push(c),
+                    _ => bail!("line {}: invalid escape in string", self.line),
+                },
+                Some('\n') | None => bail!("line {}: unterminated string", self.line),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('[') => {
+                self.next();
+                self.array()
+            }
+            Some('{') => {
+                self.next();
+                self.table()
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.next();
+                self.string(quote)
+            }
+            _ => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| anyhow!("line {}: invalid value `{word}`", self.line)),
+                }
+            }
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = match self.chars.peek() {
+            Some(&(i, _)) => i,
+            None => return String::new(),
+        };
+        let mut end = start;
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+') {
+                break;
+            }
+            end = i + c.len_utf8();
+            self.next();
+        }
+        self.text[start..end].to_string()
+    }
+}
+
+/// Parses a list of category names separated by commas or whitespace, as in
+/// `"use, fn, typedef"`.
+pub(crate) fn parse_category_list(list: &str) -> Result<Vec<Category>> {
+    let names = list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(|name| Value::String(name.to_string()))
+        .collect();
+    parse_category_order(Value::Array(names))
+}
+
+fn parse_blank_lines(value: Value) -> Result<usize> {
+    match value {
+        Value::Integer(n) if (0..=MAX_BLANK_LINES as i64).contains(&n) => Ok(n as usize),
+        _ => bail!("expected a number of blank lines between 0 and {MAX_BLANK_LINES}"),
+    }
+}
+
+fn parse_blank_lines_within(value: Value) -> Result<HashMap<Category, usize>> {
+    let entries = match value {
+        Value::Table(entries) => entries,
+        value => {
+            let blank_lines = parse_blank_lines(value)?;
+            return Ok(Category::DEFAULT_ORDER
+                .into_iter()
+                .chain(Category::OPTIONAL)
+                .map(|category| (category, blank_lines))
+                .collect());
+        }
+    };
+
+    let mut blank_lines_within = HashMap::new();
+    for (name, value) in entries {
+        let category: Category = name.parse()?;
+        let blank_lines = parse_blank_lines(value).with_context(|| format!("category `{name}`"))?;
+        if blank_lines_within.insert(category, blank_lines).is_some() {
+            bail!("category `{name}` is listed more than once");
+        }
+    }
+
+    Ok(blank_lines_within)
+}
+
+fn parse_category_order(value: Value) -> Result<Vec<Category>> {
+    let Value::Array(values) = value else {
+        bail!("expected an array of category names");
+    };
+
+    let mut order = Vec::new();
+    for value in values {
+        let Value::String(name) = value else {
+            bail!("expected an array of category names");
+        };
+        let category: Category = name.parse()?;
+        if order.contains(&category) {
+            bail!("category `{name}` is listed more than once");
+        }
+        order.push(category);
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_category_list() {
+        assert_eq!(
+            parse_category_list("fn, use typedef,").unwrap(),
+            [Category::Fn, Category::Use, Category::Typedef]
+        );
+        assert_eq!(
+            parse_category_list("fn, widgets").unwrap_err().to_string(),
+            "unknown category `widgets`"
+        );
+        assert!(parse_category_list("fn, fn").is_err());
+    }
+
+    #[test]
+    fn test_from_toml_empty_is_default() {
+        let config = Config::from_toml("# nothing here\n").unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_from_toml_blank_lines() {
+        let config = Config::from_toml(
+            "blank_lines_between = 2\nblank_lines_within = { fn = 0, impl = 2 }\n",
+        )
+        .unwrap();
+        assert_eq!(config.blank_lines_between, 2);
+        assert_eq!(config.blank_lines_within(Category::Fn), 0);
+        assert_eq!(config.blank_lines_within(Category::Impl), 2);
+        assert_eq!(config.blank_lines_within(Category::Trait), 1);
+        assert_eq!(config.blank_lines_within(Category::Use), 0);
+
+        let config = Config::from_toml("blank_lines_within = 2").unwrap();
+        assert_eq!(config.blank_lines_within(Category::Use), 2);
+        assert_eq!(config.blank_lines_within(Category::Fn), 2);
+    }
+
+    #[test]
+    fn test_from_toml_rejects_invalid_blank_lines() {
+        let err = Config::from_toml("blank_lines_between = 4").unwrap_err();
+        assert!(
+            format!("{err:#}").contains("between 0 and 3"),
+            "got {err:#}"
+        );
+
+        let err = Config::from_toml("blank_lines_between = -1").unwrap_err();
+        assert!(
+            format!("{err:#}").contains("between 0 and 3"),
+            "got {err:#}"
+        );
+
+        let err = Config::from_toml("blank_lines_within = { widgets = 1 }").unwrap_err();
+        assert!(format!("{err:#}").contains("unknown category `widgets`"));
+    }
+
+    #[test]
+    fn test_from_toml_category_order() {
+        let config = Config::from_toml(
+            "\
+category_order = [
+    \"use\", # imports first
+    \"impl\",
+    'fn',
+]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            config.category_order,
+            [Category::Use, Category::Impl, Category::Fn]
+        );
+        assert_eq!(
+            config.resolved_order(),
+            [
+                Category::Use,
+                Category::Impl,
+                Category::Foreign,
+                Category::ProcMacro,
+                Category::Fn,
+                Category::ExternCrate,
+                Category::PubUse,
+                Category::Macro,
+                Category::Mod,
+                Category::InlineMod,
+                Category::Type,
+                Category::Const,
+                Category::Trait,
+                Category::Typedef,
+                Category::Union,
+                Category::Tests,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unlisted_split_categories_keep_their_place() {
+        let config = Config::from_toml("category_order = [\"fn\", \"typedef\"]").unwrap();
+        assert_eq!(
+            config.resolved_order()[..5],
+            [
+                Category::Foreign,
+                Category::ProcMacro,
+                Category::Fn,
+                Category::Typedef,
+                Category::Union
+            ]
+        );
+
+        let config =
+            Config::from_toml("category_order = [\"union\", \"fn\", \"typedef\", \"foreign\"]")
+                .unwrap();
+        assert_eq!(
+            config.resolved_order()[..6],
+            [
+                Category::Union,
+                Category::ProcMacro,
+                Category::Fn,
+                Category::Typedef,
+                Category::Foreign,
+                Category::ExternCrate
+            ]
+        );
+
+        let config = Config::from_toml("category_order = [\"use\"]").unwrap();
+        assert_eq!(config.resolved_order().len(), Category::DEFAULT_ORDER.len());
+    }
+
+    #[test]
+    fn test_edition_2015_leads_with_extern_crate() {
+        let config = Config {
+            edition: "2015".parse().unwrap(),
+            ..Config::default()
+        };
+        let order = config.resolved_order();
+        assert_eq!(order[..2], [Category::ExternCrate, Category::PubUse]);
+        assert_eq!(order.len(), Category::DEFAULT_ORDER.len());
+
+        assert!("2027".parse::<Edition>().is_err());
+    }
+
+    #[test]
+    fn test_from_toml_generated_marker() {
+        let config = Config::from_toml("generated_marker = \"DO NOT EDIT\"").unwrap();
+        assert_eq!(config.generated_marker, "DO NOT EDIT");
+
+        let err = Config::from_toml("generated_marker = 1").unwrap_err();
+        assert!(format!("{err:#}").contains("expected a string"));
+    }
+
+    #[test]
+    fn test_from_toml_rejects_duplicate_category() {
+        let err = Config::from_toml("category_order = [\"fn\", \"use\", \"fn\"]").unwrap_err();
+        assert!(format!("{err:#}").contains("`fn` is listed more than once"));
+    }
+
+    #[test]
+    fn test_from_toml_rejects_unknown_category() {
+        let err = Config::from_toml("category_order = [\"use\", \"widgets\"]").unwrap_err();
+        assert!(format!("{err:#}").contains("unknown category `widgets`"));
+    }
+
+    #[test]
+    fn test_from_toml_rejects_unknown_key() {
+        let err = Config::from_toml("colour = true").unwrap_err();
+        assert!(format!("{err:#}").contains("line 1: unknown key `colour`"));
+    }
+
+    #[test]
+    fn test_parser_values() {
+        let entries = Parser::new("a = 1_000\nb = false\nc = \"x\\\"y\"\n")
+            .document()
+            .unwrap();
+        assert_eq!(
+            entries,
+            [
+                (1, "a".to_string(), Value::Integer(1000)),
+                (2, "b".to_string(), Value::Bool(false)),
+                (3, "c".to_string(), Value::String("x\"y".to_string())),
+            ]
+        );
+
+        let entries = Parser::new("t = { a = 1, b = [] }\n").document().unwrap();
+        assert_eq!(
+            entries,
+            [(
+                1,
+                "t".to_string(),
+                Value::Table(vec![
+                    ("a".to_string(), Value::Integer(1)),
+                    ("b".to_string(), Value::Array(Vec::new())),
+                ])
+            )]
+        );
+    }
+}
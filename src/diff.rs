@@ -0,0 +1,292 @@
+const BOLD: &str = "\x1b[1m";
+const CONTEXT: usize = 3;
+const CYAN: &str = "\x1b[36m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+struct Hunk {
+    edits: Vec<Edit>,
+    new_start: usize,
+    old_start: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Renders a unified diff from `old` to `new`, which is empty if they are
+/// equal. With `color`, headers, hunk ranges and changed lines are wrapped in
+/// ANSI escape codes.
+pub fn unified_diff(path: &str, old: &str, new: &str, color: bool) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+
+    let hunks = group_hunks(&edits);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let paint = |out: &mut String, style: &str, text: &str| {
+        if color {
+            out.push_str(style);
+            out.push_str(text);
+            out.push_str(RESET);
+        } else {
+            out.push_str(text);
+        }
+    };
+
+    let mut out = String::new();
+    paint(&mut out, BOLD, &format!("--- {path}\n+++ {path}"));
+    out.push('\n');
+    for hunk in hunks {
+        let old_len = hunk
+            .edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Insert(_)))
+            .count();
+        let new_len = hunk
+            .edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Delete(_)))
+            .count();
+        let range = format!(
+            "@@ -{} +{} @@",
+            hunk_range(hunk.old_start, old_len),
+            hunk_range(hunk.new_start, new_len)
+        );
+        paint(&mut out, CYAN, &range);
+        out.push('\n');
+
+        for edit in hunk.edits {
+            let (marker, line, style) = match edit {
+                Edit::Equal(i, _) => (' ', old_lines[i], ""),
+                Edit::Delete(i) => ('-', old_lines[i], RED),
+                Edit::Insert(j) => ('+', new_lines[j], GREEN),
+            };
+            let text = format!("{marker}{}", line.strip_suffix('\n').unwrap_or(line));
+            if style.is_empty() {
+                out.push_str(&text);
+            } else {
+                paint(&mut out, style, &text);
+            }
+            out.push('\n');
+            if !line.ends_with('\n') {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    out
+}
+
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        if d == 0 {
+            while x > 0 && y > 0 {
+                x -= 1;
+                y -= 1;
+                edits.push(Edit::Equal(x as usize, y as usize));
+            }
+            break;
+        }
+
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if x == prev_x {
+            edits.push(Edit::Insert(prev_y as usize));
+        } else {
+            edits.push(Edit::Delete(prev_x as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+fn group_hunks(edits: &[Edit]) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for idx in changes {
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(edits.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let (mut old_start, mut new_start) = (0, 0);
+            for edit in &edits[..start] {
+                match edit {
+                    Edit::Equal(..) => {
+                        old_start += 1;
+                        new_start += 1;
+                    }
+                    Edit::Delete(_) => old_start += 1,
+                    Edit::Insert(_) => new_start += 1,
+                }
+            }
+            Hunk {
+                edits: edits[start..end].to_vec(),
+                new_start,
+                old_start,
+            }
+        })
+        .collect()
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_color() {
+        let old = "fn b() {}\nfn a() {}\n";
+        let new = "fn a() {}\nfn b() {}\n";
+        assert_eq!(
+            unified_diff("a.rs", old, new, true),
+            "\
+\x1b[1m--- a.rs
++++ a.rs\x1b[0m
+\x1b[36m@@ -1,2 +1,2 @@\x1b[0m
+\x1b[31m-fn b() {}\x1b[0m
+ fn a() {}
+\x1b[32m+fn b() {}\x1b[0m
+"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_identical() {
+        assert_eq!(
+            unified_diff("a.rs", "fn a() {}\n", "fn a() {}\n", false),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_swap() {
+        let old = "fn b() {}\nfn a() {}\n";
+        let new = "fn a() {}\nfn b() {}\n";
+        assert_eq!(
+            unified_diff("a.rs", old, new, false),
+            "\
+--- a.rs
++++ a.rs
+@@ -1,2 +1,2 @@
+-fn b() {}
+ fn a() {}
++fn b() {}
+"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_separate_hunks() {
+        let old = "x\n1\n2\n3\n4\n5\n6\n7\n8\ny\n";
+        let new = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        assert_eq!(
+            unified_diff("a.rs", old, new, false),
+            "\
+--- a.rs
++++ a.rs
+@@ -1,4 +1,3 @@
+-x
+ 1
+ 2
+ 3
+@@ -7,4 +6,3 @@
+ 6
+ 7
+ 8
+-y
+"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_no_trailing_newline() {
+        assert_eq!(
+            unified_diff("a.rs", "a\n", "a", false),
+            "\
+--- a.rs
++++ a.rs
+@@ -1 +1 @@
+-a
++a
+\\ No newline at end of file
+"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_refmt::{Config, LineEnding};
+
+use crate::ignore::{glob_match, slash_path};
+
+/// A `[pattern]` section of an `.editorconfig` file.
+struct Section {
+    pattern: String,
+    /// The `key = value` pairs of the section, with keys in lowercase.
+    properties: Vec<(String, String)>,
+}
+
+/// Sets the line ending and final newline of `config` from the
+/// `end_of_line` and `insert_final_newline` properties that the
+/// `.editorconfig` files in the directories above `path` give it.
+///
+/// As in other editors, the search stops at a file with `root = true`,
+/// nearer files take precedence over those further up and later sections
+/// take precedence over earlier ones.
+pub fn apply(path: &Path, config: &mut Config) -> Result<()> {
+    let path =
+        std::path::absolute(path).with_context(|| format!("resolve path {}", path.display()))?;
+
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let file = dir.join(".editorconfig");
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("read editorconfig {}", file.display()));
+            }
+        };
+        let (root, sections) = parse(&text);
+        files.push((dir, sections));
+        if root {
+            break;
+        }
+    }
+
+    for (dir, sections) in files.iter().rev() {
+        let relative = slash_path(path.strip_prefix(dir).unwrap_or(&path));
+        for section in sections {
+            if !section_matches(&section.pattern, &relative) {
+                continue;
+            }
+            for (key, value) in &section.properties {
+                match (key.as_str(), value.to_ascii_lowercase().as_str()) {
+                    ("end_of_line", "crlf") => config.line_ending = Some(LineEnding::Crlf),
+                    ("end_of_line", "lf") => config.line_ending = Some(LineEnding::Lf),
+                    ("end_of_line", "unset") => config.line_ending = None,
+                    ("insert_final_newline", "false") => config.final_newline = Some(false),
+                    ("insert_final_newline", "true") => config.final_newline = Some(true),
+                    ("insert_final_newline", "unset") => config.final_newline = None,
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expands the first `{a,b}` alternation in `pattern`, and recursively the
+/// rest, into the patterns it stands for.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for (offset, c) in pattern[open..].char_indices() {
+        let index = open + offset;
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => {
+                alternatives.push(&pattern[start..index]);
+                // A single alternative, as in `{a}`, is matched literally.
+                if alternatives.len() < 2 {
+                    break;
+                }
+                let rest = expand_braces(&pattern[index + 1..]);
+                return alternatives
+                    .iter()
+                    .flat_map(|alternative| {
+                        expand_braces(&format!("{}{alternative}", &pattern[..open]))
+                    })
+                    .flat_map(|prefix| rest.iter().map(move |rest| format!("{prefix}{rest}")))
+                    .collect();
+            }
+            '}' => depth -= 1,
+            ',' if depth == 1 => {
+                alternatives.push(&pattern[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    // Braces that do not form an alternation are kept as they are.
+    expand_braces(&pattern[open + 1..])
+        .into_iter()
+        .map(|rest| format!("{}{rest}", &pattern[..=open]))
+        .collect()
+}
+
+/// Parses an `.editorconfig` file into whether it is marked `root = true` and
+/// its sections. Properties before the first section only count for `root`.
+fn parse(text: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(pattern) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            sections.push(Section {
+                pattern: pattern.to_string(),
+                properties: Vec::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim().to_string();
+        match sections.last_mut() {
+            Some(section) => section.properties.push((key, value)),
+            None if key == "root" => root = value.eq_ignore_ascii_case("true"),
+            None => {}
+        }
+    }
+    (root, sections)
+}
+
+/// Returns whether the section `pattern` applies to `path`, given relative to
+/// the directory of the `.editorconfig` file. Patterns without a `/` match
+/// files of that name in any subdirectory.
+fn section_matches(pattern: &str, path: &str) -> bool {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{pattern}")
+    };
+    expand_braces(&pattern)
+        .iter()
+        .any(|pattern| glob_match(pattern, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("*.rs"), ["*.rs"]);
+        assert_eq!(expand_braces("*.{rs,toml}"), ["*.rs", "*.toml"]);
+        assert_eq!(
+            expand_braces("{src,tests}/*.{rs,md}"),
+            ["src/*.rs", "src/*.md", "tests/*.rs", "tests/*.md"]
+        );
+        assert_eq!(expand_braces("{a,{b,c}}.rs"), ["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(expand_braces("{single}.rs"), ["{single}.rs"]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let (root, sections) =
+            parse("root = true\n\n# Comment\n[*]\nend_of_line = crlf\n[*.md]\nINDENT_SIZE=2\n");
+
+        assert!(root);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].pattern, "*");
+        assert_eq!(
+            sections[0].properties,
+            [("end_of_line".to_string(), "crlf".to_string())]
+        );
+        assert_eq!(
+            sections[1].properties,
+            [("indent_size".to_string(), "2".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_section_matches() {
+        assert!(section_matches("*", "lib.rs"));
+        assert!(section_matches("*.rs", "src/a/lib.rs"));
+        assert!(section_matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(section_matches("src/*.rs", "src/lib.rs"));
+        assert!(section_matches("/src/**/*.rs", "src/a/lib.rs"));
+        assert!(!section_matches("src/*.rs", "tests/src/lib.rs"));
+        assert!(!section_matches("*.md", "lib.rs"));
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+
+/// The rules of a single `.gitignore` file.
+pub struct Gitignore {
+    dir: PathBuf,
+    prefix: String,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+    pattern: String,
+}
+
+impl Gitignore {
+    /// Loads `dir/.gitignore`, returning `None` when there is no such file.
+    pub fn load(dir: &Path) -> Result<Option<Gitignore>> {
+        let path = dir.join(".gitignore");
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read ignore file {}", path.display()));
+            }
+        };
+        Ok(Some(Gitignore {
+            dir: dir.to_path_buf(),
+            prefix: String::new(),
+            rules: text.lines().filter_map(Rule::parse).collect(),
+        }))
+    }
+
+    /// Applies the rules of an ancestor directory to paths under `dir`, which
+    /// is at `prefix` relative to that ancestor.
+    pub fn with_base(self, dir: &Path, prefix: String) -> Gitignore {
+        Gitignore {
+            dir: dir.to_path_buf(),
+            prefix,
+            rules: self.rules,
+        }
+    }
+
+    /// Returns `Some(true)` if the last rule matching `path` ignores it,
+    /// `Some(false)` if it re-includes it and `None` if no rule matches.
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let relative_str = self.prefix.clone() + &slash_path(relative);
+        let name = relative_str.rsplit('/').next().unwrap_or_default();
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && if rule.anchored {
+                        glob_match(&rule.pattern, &relative_str)
+                    } else {
+                        glob_match(&rule.pattern, name)
+                    }
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let mut pattern = line.trim_end_matches([' ', '\t', '\r']);
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return None;
+        }
+
+        let negated = pattern.starts_with('!');
+        if negated || pattern.starts_with("\\#") || pattern.starts_with("\\!") {
+            pattern = &pattern[1..];
+        }
+
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let anchored = pattern.contains('/');
+
+        Some(Rule {
+            anchored,
+            dir_only,
+            negated,
+            pattern: pattern.trim_start_matches('/').to_string(),
+        })
+    }
+}
+
+/// Matches `path` against a glob `pattern`.
+///
+/// `*` and `?` match within a single path component, `**` matches across
+/// components and `[...]` matches a character class, which may be negated
+/// with a leading `!` or `^`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    match_from(&pattern, &path)
+}
+
+/// Returns whether the nearest `.gitignore` rules ignore `path`.
+pub fn is_ignored(gitignores: &[Rc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+    gitignores
+        .iter()
+        .rev()
+        .find_map(|gitignore| gitignore.decide(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Joins the normal components of `path` with `/`, dropping any `.` segments.
+pub fn slash_path(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .filter(|component| !matches!(component, std::path::Component::CurDir))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
+
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&start) = pattern.get(i) {
+        if start == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+fn match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let mut rest = &pattern[2..];
+            if rest.first() == Some(&'/') {
+                rest = &rest[1..];
+                // `**/` also matches zero directories.
+                if match_from(rest, path) {
+                    return true;
+                }
+            }
+            (0..path.len()).any(|i| {
+                (i == 0 || path[i - 1] == '/' || rest.is_empty()) && match_from(rest, &path[i..])
+            }) || rest.is_empty()
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=path.len() {
+                if match_from(rest, &path[i..]) {
+                    return true;
+                }
+                if path.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            path.first().is_some_and(|&c| c != '/') && match_from(&pattern[1..], &path[1..])
+        }
+        Some('[') => {
+            let Some(&c) = path.first() else {
+                return false;
+            };
+            match match_class(pattern, c) {
+                Some((matched, len)) => {
+                    c != '/' && matched && match_from(&pattern[len..], &path[1..])
+                }
+                // An unterminated class matches a literal `[`.
+                None => c == '[' && match_from(&pattern[1..], &path[1..]),
+            }
+        }
+        Some('\\') if pattern.len() > 1 => {
+            path.first() == Some(&pattern[1]) && match_from(&pattern[2..], &path[1..])
+        }
+        Some(&c) => path.first() == Some(&c) && match_from(&pattern[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "lib.rs"));
+        assert!(!glob_match("*.rs", "src/lib.rs"));
+        assert!(glob_match("src/*_generated.rs", "src/api_generated.rs"));
+        assert!(glob_match("src/**", "src/a/b.rs"));
+        assert!(glob_match("**/bindings", "bindings"));
+        assert!(glob_match("**/bindings", "src/ffi/bindings"));
+        assert!(glob_match("a/**/b.rs", "a/b.rs"));
+        assert!(glob_match("a/**/b.rs", "a/x/y/b.rs"));
+        assert!(!glob_match("a/**/b.rs", "a/xb.rs"));
+        assert!(glob_match("mod?.rs", "mod1.rs"));
+        assert!(glob_match("[a-c]*.rs", "build.rs"));
+        assert!(!glob_match("[!a-c]*.rs", "build.rs"));
+        assert!(glob_match("\\*.rs", "*.rs"));
+        assert!(!glob_match("\\*.rs", "a.rs"));
+    }
+
+    #[test]
+    fn test_rule_parse() {
+        assert!(Rule::parse("# comment").is_none());
+        assert!(Rule::parse("   ").is_none());
+
+        let rule = Rule::parse("!/target/  ").unwrap();
+        assert!(rule.anchored && rule.dir_only && rule.negated);
+        assert_eq!(rule.pattern, "target");
+
+        let rule = Rule::parse("\\#file.rs").unwrap();
+        assert!(!rule.anchored && !rule.dir_only && !rule.negated);
+        assert_eq!(rule.pattern, "#file.rs");
+    }
+}
@@ -0,0 +1,2972 @@
+pub use config::{Config, Edition, LineEnding};
+
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+use anyhow::{Context, Result, anyhow, bail};
+use config::parse_category_list;
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Attribute, File, Item, Token};
+
+mod config;
+
+/// The number of characters between the checkpoints of [`LineStarts`].
+const CHECKPOINT_CHARS: usize = 64;
+
+struct Entry {
+    /// Items emitted directly after this one, such as its `impl` blocks.
+    attached: Vec<Entry>,
+    category: Category,
+    group: usize,
+    /// 1-based line the item started on in the source.
+    line: usize,
+    /// Items carried verbatim in `text`, with their offset in it, reported in
+    /// place of the entry itself.
+    members: Vec<(usize, ItemMove)>,
+    name: Option<String>,
+    public: Option<bool>,
+    self_type: Option<String>,
+    text: String,
+    /// Whether this is an `impl` block that `attach_impls` found no type for.
+    unmatched: bool,
+    use_path: Vec<String>,
+}
+
+/// Why a top-level item is placed in the section it is, as reported by
+/// [`explain_items`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemExplanation {
+    /// Section the item is sorted into.
+    pub category: Category,
+    /// Position of the section in the resolved category order.
+    pub index: usize,
+    /// Variant of [`syn::Item`] the item parsed as, such as `Fn`.
+    pub kind: &'static str,
+    /// 1-based line the item starts on, counting its doc comments and
+    /// attributes.
+    pub line: usize,
+    /// Identifier of the item, if it has one.
+    pub name: Option<String>,
+    /// What made the item test-only, if anything: a `cfg(test)` module, some
+    /// other `cfg(test)` item or a `#[test]` function.
+    pub test: Option<&'static str>,
+}
+
+/// Where reordering placed a top-level item.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemMove {
+    /// Section the item was sorted into.
+    pub category: Category,
+    /// Identifier of the item, if it has one.
+    pub name: Option<String>,
+    /// 1-based line the item starts on in the reordered source, counting its
+    /// doc comments and attributes.
+    pub new_line: usize,
+    /// 1-based line the item started on in the original source.
+    pub original_line: usize,
+    /// For an `impl` block that [`Config::impls_with_types`] found no local
+    /// struct, enum or union for, the name of the type it implements.
+    pub unmatched_self_type: Option<String>,
+}
+
+/// The byte offsets at which the lines of a source text start, followed by
+/// the length of the text when it does not end with a newline.
+struct LineStarts {
+    /// The number of ASCII bytes at the start of each line, within which
+    /// columns and byte offsets agree.
+    ascii_lengths: Vec<usize>,
+    /// The byte offsets of every [`CHECKPOINT_CHARS`]th character past the
+    /// leading ASCII bytes of each line, from which a column is walked to.
+    checkpoints: Vec<usize>,
+    /// The index in `checkpoints` of the first checkpoint of each line.
+    first_checkpoints: Vec<usize>,
+    starts: Vec<usize>,
+}
+
+/// A `// region` ... `// endregion` block of items.
+struct Region {
+    /// Indices of the items in the region.
+    items: std::ops::RangeInclusive<usize>,
+    /// Extent of the region in the source, from its opening marker to the end
+    /// of its closing one.
+    text: std::ops::Range<usize>,
+}
+
+/// A section of the output that top-level items are grouped into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// `pub use` re-exports, grouped like other `use` declarations.
+    PubUse,
+    /// `use` declarations, grouped by std, external and local origin.
+    Use,
+    /// `macro_rules!` definitions, kept ahead of the code that invokes them.
+    Macro,
+    /// Module declarations such as `mod foo;`.
+    Mod,
+    /// Inline modules with a body.
+    InlineMod,
+    /// `extern crate` declarations.
+    ExternCrate,
+    /// Type aliases.
+    Type,
+    /// Constants, and statics unless `static` is in the category order.
+    Const,
+    /// Statics, separated from constants only when listed in the category
+    /// order, along with `static mut` items unless `static_mut` is too.
+    Static,
+    /// Mutable statics, separated only when listed in the category order.
+    StaticMut,
+    /// Traits, and trait aliases unless `trait_alias` is in the category order.
+    Trait,
+    /// Trait aliases, separated from traits only when listed in the category
+    /// order.
+    TraitAlias,
+    /// Structs and enums.
+    Typedef,
+    /// Unions, which are mostly used for FFI.
+    Union,
+    /// Inherent and trait implementations.
+    Impl,
+    /// Foreign blocks such as `extern "C" { ... }`.
+    Foreign,
+    /// Functions marked `#[proc_macro]`, `#[proc_macro_derive]` or
+    /// `#[proc_macro_attribute]`, the API of a proc-macro crate.
+    ProcMacro,
+    /// Functions, macro invocations and anything else.
+    Fn,
+    /// `async fn` functions, separated from the others only when listed in
+    /// the category order.
+    AsyncFn,
+    /// `#[cfg(test)]` items, such as test modules, and `#[test]` functions.
+    Tests,
+}
+
+impl std::ops::Deref for LineStarts {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.starts
+    }
+}
+
+impl Category {
+    /// Every category in the order used when no configuration is given.
+    pub const DEFAULT_ORDER: [Category; 16] = [
+        Category::ExternCrate,
+        Category::PubUse,
+        Category::Use,
+        Category::Macro,
+        Category::Mod,
+        Category::InlineMod,
+        Category::Type,
+        Category::Const,
+        Category::Trait,
+        Category::Typedef,
+        Category::Union,
+        Category::Impl,
+        Category::Foreign,
+        Category::ProcMacro,
+        Category::Fn,
+        Category::Tests,
+    ];
+
+    /// Categories that only get a section of their own when listed in the
+    /// category order, and otherwise share the section they split off from.
+    pub const OPTIONAL: [Category; 4] = [
+        Category::Static,
+        Category::StaticMut,
+        Category::TraitAlias,
+        Category::AsyncFn,
+    ];
+
+    /// Returns the name used for this category in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::PubUse => "pub_use",
+            Category::Use => "use",
+            Category::Macro => "macro",
+            Category::Mod => "mod",
+            Category::InlineMod => "inline_mod",
+            Category::ExternCrate => "extern_crate",
+            Category::Type => "type",
+            Category::Const => "const",
+            Category::Static => "static",
+            Category::StaticMut => "static_mut",
+            Category::Trait => "trait",
+            Category::TraitAlias => "trait_alias",
+            Category::Typedef => "typedef",
+            Category::Union => "union",
+            Category::Impl => "impl",
+            Category::Foreign => "foreign",
+            Category::ProcMacro => "proc_macro",
+            Category::Fn => "fn",
+            Category::AsyncFn => "async_fn",
+            Category::Tests => "tests",
+        }
+    }
+
+    fn is_use(self) -> bool {
+        matches!(self, Category::PubUse | Category::Use)
+    }
+
+    fn blank_lines_within(self) -> usize {
+        match self {
+            Category::PubUse
+            | Category::Use
+            | Category::Mod
+            | Category::ExternCrate
+            | Category::Type
+            | Category::Const
+            | Category::Static
+            | Category::StaticMut => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl std::str::FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Category::DEFAULT_ORDER
+            .into_iter()
+            .chain(Category::OPTIONAL)
+            .find(|category| category.name() == name)
+            .ok_or_else(|| anyhow!("unknown category `{name}`"))
+    }
+}
+
+/// Returns the output section an item is placed in, before any configuration
+/// merges or splits sections. Statics are placed with constants.
+pub fn category(item: &Item) -> Category {
+    if has_cfg_test(item_attributes(item)) || is_test_fn(item) {
+        return Category::Tests;
+    }
+
+    match item {
+        Item::Use(use_item) if matches!(use_item.vis, syn::Visibility::Public(_)) => {
+            Category::PubUse
+        }
+        Item::Use(_) => Category::Use,
+        Item::Macro(item_macro) if item_macro.ident.is_some() => Category::Macro,
+        Item::Mod(item_mod) if item_mod.content.is_some() => Category::InlineMod,
+        Item::Mod(_) => Category::Mod,
+        Item::ExternCrate(_) => Category::ExternCrate,
+        Item::Type(_) => Category::Type,
+        Item::Const(_) | Item::Static(_) => Category::Const,
+        Item::Trait(_) | Item::TraitAlias(_) => Category::Trait,
+        Item::Struct(_) | Item::Enum(_) => Category::Typedef,
+        Item::Union(_) => Category::Union,
+        Item::Impl(_) => Category::Impl,
+        Item::ForeignMod(_) => Category::Foreign,
+        Item::Fn(item_fn) if is_proc_macro(&item_fn.attrs) => Category::ProcMacro,
+        Item::Fn(_) | Item::Macro(_) | Item::Verbatim(_) => Category::Fn,
+        _ => Category::Fn,
+    }
+}
+
+/// Returns the number of bytes of `src` taken up by `//` and `/* */`
+/// comments, including doc comments, ignoring anything inside string and
+/// character literals.
+///
+/// Comparing the count before and after reordering reveals comments that
+/// reordering dropped.
+pub fn comment_bytes(src: &str) -> usize {
+    let bytes = src.as_bytes();
+    let mut total = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let is_ident_before =
+            i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = src[i..].find('\n').map_or(src.len(), |offset| i + offset);
+                total += end - i;
+                i = end;
+            }
+            (b'/', Some(b'*')) => {
+                let end = block_comment_end(bytes, i);
+                total += end - i;
+                i = end;
+            }
+            (b'"', _) => i = string_end(bytes, i + 1),
+            (b'r' | b'b' | b'c', _) if !is_ident_before => {
+                i = raw_string_end(bytes, i).unwrap_or(i + 1);
+            }
+            (b'\'', _) => {
+                // Character literals hold one (possibly escaped) character;
+                // anything else is a lifetime or label.
+                let rest = &src[i + 1..];
+                let literal_len = match rest.chars().next() {
+                    Some('\\') => rest
+                        .get(2..)
+                        .and_then(|escaped| escaped.find('\''))
+                        .map(|offset| offset + 3),
+                    Some(c) if rest[c.len_utf8()..].starts_with('\'') => Some(c.len_utf8() + 1),
+                    _ => None,
+                };
+                i += 1 + literal_len.unwrap_or(0);
+            }
+            _ => i += 1,
+        }
+    }
+
+    total
+}
+
+/// Explains, in source order, which section each top-level item of `src` is
+/// placed in by [`reorder_source_with`] and why, without reordering anything.
+/// Skipped and generated sources have no items to explain.
+pub fn explain_items(src: &str, config: &Config) -> Result<Vec<ItemExplanation>> {
+    let src = src.strip_prefix('\u{feff}').unwrap_or(src);
+    if has_skip_directive(src) || is_generated(src, config) {
+        return Ok(Vec::new());
+    }
+    let src = src.replace("\r\n", "\n");
+    let file = syn::parse_file(&src)?;
+    let file_config = with_file_order(&file.attrs, config)?;
+    let config = file_config.as_ref().unwrap_or(config);
+    let line_starts = line_start_offsets(&src);
+    let order = config.resolved_order();
+
+    Ok(file
+        .items
+        .iter()
+        .map(|item| {
+            let category = section(item, config);
+            let test = if is_test_module(item) {
+                Some("cfg(test) module")
+            } else if has_cfg_test(item_attributes(item)) {
+                Some("cfg(test)")
+            } else if is_test_fn(item) {
+                Some("#[test]")
+            } else {
+                None
+            };
+            let start = item_range(item, &src, &line_starts).start;
+            ItemExplanation {
+                category,
+                index: order
+                    .iter()
+                    .position(|c| *c == category)
+                    .expect("category order covers every category"),
+                kind: item_kind(item),
+                line: line_starts.partition_point(|&line_start| line_start <= start),
+                name: item_name(item),
+                test,
+            }
+        })
+        .collect())
+}
+
+/// Returns whether a comment in the first lines of `src` carries the
+/// configured generated-code marker, `@generated` by default.
+pub fn is_generated(src: &str, config: &Config) -> bool {
+    const SCANNED_LINES: usize = 5;
+
+    if config.generated_marker.is_empty() {
+        return false;
+    }
+    src.lines().take(SCANNED_LINES).any(|line| {
+        let line = line.trim_start();
+        (line.starts_with("//") || line.starts_with("/*") || line.starts_with('*'))
+            && line.contains(&config.generated_marker)
+    })
+}
+
+/// Reorders `src` like [`reorder_source_with`] and returns where each
+/// top-level item was placed, in output order. Skipped and generated sources
+/// have no items to report.
+pub fn item_moves(src: &str, config: &Config) -> Result<Vec<ItemMove>> {
+    let src = src.strip_prefix('\u{feff}').unwrap_or(src);
+    if has_skip_directive(src) || is_generated(src, config) {
+        return Ok(Vec::new());
+    }
+    let (_, moves) = reorder_pass(&src.replace("\r\n", "\n"), config)?;
+    Ok(moves)
+}
+
+/// Reorders only the top-level items that lie entirely within the 1-based,
+/// inclusive range of `lines`, leaving the rest of `src` byte for byte as it
+/// was.
+///
+/// Items that straddle either end of the range are not moved, and neither is
+/// anything else outside it. Otherwise this behaves like
+/// [`reorder_source_with`].
+pub fn reorder_range(src: &str, config: &Config, lines: RangeInclusive<usize>) -> Result<String> {
+    reorder_text(src, config, &|src| reorder_lines(src, config, &lines))
+}
+
+/// Reorders the top-level items of a Rust source file and returns the result.
+pub fn reorder_source(src: &str) -> Result<String> {
+    reorder_source_with(src, &Config::default())
+}
+
+/// Reorders the top-level items of a Rust source file using the given configuration.
+///
+/// Sources whose first line is a `// reorder:skip` comment, and generated
+/// sources (see [`is_generated`]), are returned unchanged.
+/// The output uses the dominant line ending (LF or CRLF) of the input and keeps
+/// a leading UTF-8 byte order mark.
+///
+/// Every option is a field of [`Config`], so embedders can set them from code
+/// without a configuration file:
+///
+/// ```
+/// use cargo_refmt::{Category, Config, reorder_source_with};
+///
+/// let config = Config {
+///     blank_lines_between: 2,
+///     category_order: vec![Category::Fn, Category::Use],
+///     pub_first: true,
+///     sort_within: true,
+///     ..Config::default()
+/// };
+/// let src = "use std::fs;\nfn b() {}\nfn a() {}\npub fn c() {}\n";
+/// let out = reorder_source_with(src, &config).unwrap();
+/// assert_eq!(out, "pub fn c() {}\n\nfn a() {}\n\nfn b() {}\n\n\nuse std::fs;\n");
+/// ```
+pub fn reorder_source_with(src: &str, config: &Config) -> Result<String> {
+    reorder_text(src, config, &|src| {
+        let out = match reorder_normalized(src, config) {
+            Err(err) if config.force_partial => match err.downcast::<syn::Error>() {
+                Ok(err) => reorder_partial(src, config, err)?,
+                Err(err) => return Err(err),
+            },
+            result => result?,
+        };
+        Ok(if config.trim_blank_lines {
+            trim_blank_lines(&out)
+        } else {
+            out
+        })
+    })
+}
+
+fn attach_impls(order: &[Category], buckets: &mut [Vec<Entry>]) {
+    let slot = |cat| {
+        order
+            .iter()
+            .position(|c| *c == cat)
+            .expect("resolved order")
+    };
+    let impls = std::mem::take(&mut buckets[slot(Category::Impl)]);
+    // The first type of each name in either bucket, found once rather than
+    // for every impl.
+    let mut types: HashMap<String, (usize, usize)> = HashMap::new();
+    for bucket in [Category::Typedef, Category::Union].into_iter().map(slot) {
+        for (index, entry) in buckets[bucket].iter().enumerate() {
+            if let Some(name) = &entry.name {
+                types.entry(name.clone()).or_insert((bucket, index));
+            }
+        }
+    }
+
+    // The bucket is still in source order, so impls of one type, such as
+    // those gated by different `#[cfg]`s, keep their relative order.
+    let mut unmatched = Vec::new();
+    for entry in impls {
+        let target = entry.self_type.as_ref().and_then(|name| types.get(name));
+        match target.copied() {
+            Some((bucket, index)) => buckets[bucket][index].attached.push(entry),
+            None => unmatched.push(Entry {
+                unmatched: true,
+                ..entry
+            }),
+        }
+    }
+
+    buckets[slot(Category::Impl)] = unmatched;
+}
+
+/// Returns the index just past the `*/` that closes the block comment
+/// starting at `start`, counting the comments nested within it.
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut end = start;
+    while end < bytes.len() {
+        if bytes[end..].starts_with(b"/*") {
+            depth += 1;
+            end += 2;
+        } else if bytes[end..].starts_with(b"*/") {
+            depth -= 1;
+            end += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            end += 1;
+        }
+    }
+    end
+}
+
+/// Returns the predicates of the `#[cfg(...)]` attributes in `attrs`.
+fn cfg_predicates(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::List(list) if list.path.is_ident("cfg") => Some(list.tokens.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collapses each run of blank lines among the comments and attributes in
+/// front of `item`, at the start of its `snippet`, to a single blank line.
+/// The item itself, including its body, is left as it is.
+fn collapse_header_blank_lines(
+    item: &Item,
+    src: &str,
+    line_starts: &LineStarts,
+    snippet: String,
+) -> String {
+    let start = item_range(item, src, line_starts).start;
+    let mut end = span_range(item.span(), src, line_starts).start;
+    for attr in item_attributes(item) {
+        if matches!(attr.style, syn::AttrStyle::Outer) {
+            end = end.max(span_range(attr.span(), src, line_starts).end);
+        }
+    }
+    // Comments between the last attribute and the item belong to the header.
+    loop {
+        let rest = src[end..].trim_start();
+        end = src.len() - rest.len();
+        if rest.starts_with("//") {
+            end += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            end += comment.find("*/").map_or(rest.len(), |close| close + 4);
+        } else {
+            break;
+        }
+    }
+
+    let (header, rest) = snippet.split_at((end - start).min(snippet.len()));
+    let mut out = String::with_capacity(snippet.len());
+    let mut prev_blank = false;
+    for line in header.split_inclusive('\n') {
+        let blank = line.ends_with('\n') && line.trim().is_empty();
+        if !(blank && prev_blank) {
+            out.push_str(line);
+        }
+        prev_blank = blank;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn column_to_byte(src: &str, line_base: usize, column: usize) -> usize {
+    #[cfg(test)]
+    tests::COLUMN_STEPS.with(|steps| steps.set(steps.get() + column));
+    src[line_base..]
+        .char_indices()
+        .nth(column)
+        .map_or(src.len(), |(offset, _)| line_base + offset)
+}
+
+/// Returns the end of the blank and `//` comment lines from `pos` on.
+fn comment_lines_end(src: &str, pos: usize) -> usize {
+    let mut end = pos;
+    while end < src.len() {
+        let line_end = src[end..].find('\n').map_or(src.len(), |i| end + i);
+        let line = src[end..line_end].trim();
+        if !line.is_empty() && !line.starts_with("//") {
+            break;
+        }
+        end = (line_end + 1).min(src.len());
+    }
+    end
+}
+
+/// Returns the start of the blank and `//` comment lines directly above the
+/// line containing `pos`.
+fn comment_lines_start(src: &str, pos: usize) -> usize {
+    let mut start = src[..pos].rfind('\n').map_or(0, |i| i + 1);
+    while start > 0 {
+        let prev = src[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        let line = src[prev..start - 1].trim();
+        if !line.is_empty() && !line.starts_with("//") {
+            break;
+        }
+        start = prev;
+    }
+    start
+}
+
+fn contains_test(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Path(path) => path.path.is_ident("test"),
+        syn::Expr::Tuple(tuple) => tuple.elems.iter().any(contains_test),
+        syn::Expr::Binary(bin) => contains_test(&bin.left) || contains_test(&bin.right),
+        syn::Expr::Group(group) => contains_test(&group.expr),
+        syn::Expr::Call(call) => {
+            if let syn::Expr::Path(path) = &*call.func
+                && (path.path.is_ident("any") || path.path.is_ident("all"))
+            {
+                return call.args.iter().any(contains_test);
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Appends `entry` and the entries attached to it to `out`, recording the
+/// offset each one starts at.
+fn emit_entry(
+    entry: Entry,
+    out: &mut String,
+    moves: &mut Vec<(usize, ItemMove)>,
+    config: &Config,
+    indent: &str,
+) {
+    let item_move = ItemMove {
+        category: entry.category,
+        name: entry.name,
+        new_line: 0,
+        original_line: entry.line,
+        unmatched_self_type: entry.self_type.filter(|_| entry.unmatched),
+    };
+    if entry.members.is_empty() {
+        moves.push((out.len(), item_move));
+    }
+    for (offset, member) in entry.members {
+        moves.push((out.len() + indent.len() + offset, member));
+    }
+    out.push_str(indent);
+    out.push_str(entry.text.trim_end_matches('\n'));
+    out.push('\n');
+    for attached in entry.attached {
+        out.push_str(&"\n".repeat(config.blank_lines_within(attached.category)));
+        emit_entry(attached, out, moves, config, indent);
+    }
+}
+
+/// Returns the snippet of a struct with its named fields, or of an enum with
+/// its variants, sorted by name. Comments and attributes move with the member
+/// they are attached to, and the whitespace between members stays in place.
+///
+/// Returns `None` for items with fewer than two members and for items whose
+/// meaning depends on member order: those with a `repr(C)` or `repr(packed)`
+/// layout, and those deriving `PartialOrd` or `Ord`.
+fn fields_snippet(item: &Item, src: &str, line_starts: &LineStarts) -> Option<String> {
+    let (attrs, members): (_, Vec<_>) = match item {
+        Item::Struct(item) => match &item.fields {
+            syn::Fields::Named(fields) => (
+                &item.attrs,
+                fields
+                    .named
+                    .pairs()
+                    .map(|pair| {
+                        let field = pair.value();
+                        let name = field.ident.as_ref().map(|ident| ident.unraw().to_string());
+                        (
+                            name.unwrap_or_default(),
+                            &field.attrs,
+                            field.span(),
+                            pair.punct().map(|comma| comma.span()),
+                        )
+                    })
+                    .collect(),
+            ),
+            _ => return None,
+        },
+        Item::Enum(item) => (
+            &item.attrs,
+            item.variants
+                .pairs()
+                .map(|pair| {
+                    let variant = pair.value();
+                    (
+                        variant.ident.unraw().to_string(),
+                        &variant.attrs,
+                        variant.span(),
+                        pair.punct().map(|comma| comma.span()),
+                    )
+                })
+                .collect(),
+        ),
+        _ => return None,
+    };
+    if members.len() < 2 || has_order_dependent_layout(attrs) {
+        return None;
+    }
+
+    // Each member spans its leading comments and attributes up to the end of
+    // its own text, followed by its comma and a comment on the same line.
+    let slots: Vec<_> = members
+        .iter()
+        .map(|(name, attrs, span, comma)| {
+            let mut start = span_range(*span, src, line_starts).start;
+            for attr in attrs.iter() {
+                start = start.min(span_range(attr.span(), src, line_starts).start);
+            }
+            let end = span_range(*span, src, line_starts).end;
+            let comma_end = comma.map_or(end, |comma| span_range(comma, src, line_starts).end);
+            let slot_end = trailing_comment_end(src, comma_end);
+            (
+                name,
+                leading_comment_start(src, start)..end,
+                comma_end..slot_end,
+            )
+        })
+        .collect();
+    let trailing_comma = members.last().is_some_and(|(.., comma)| comma.is_some());
+
+    let mut sorted: Vec<_> = slots.iter().collect();
+    sorted.sort_by_key(|(name, ..)| *name);
+    if sorted.iter().zip(&slots).all(|(a, b)| a.1 == b.1) {
+        return None;
+    }
+
+    let range = item_range(item, src, line_starts);
+    let mut text = src[range.start..slots[0].1.start].to_string();
+    for (index, (_, member, tail)) in sorted.iter().enumerate() {
+        if index > 0 {
+            text.push_str(&src[slots[index - 1].2.end..slots[index].1.start]);
+        }
+        text.push_str(&src[member.clone()]);
+        if index + 1 < slots.len() || trailing_comma {
+            text.push(',');
+        }
+        text.push_str(&src[tail.clone()]);
+    }
+    text.push_str(&src[slots[slots.len() - 1].2.end..range.end]);
+    Some(text.trim_end().to_string())
+}
+
+/// Returns, for each named item, the other names mentioned within its
+/// definition, which is located by the byte range in `name_to_range`.
+fn find_references(
+    names: &[String],
+    name_to_range: &HashMap<String, (usize, usize)>,
+    src: &str,
+) -> HashMap<String, Vec<String>> {
+    let mut refs: HashMap<String, Vec<String>> = HashMap::new();
+
+    for name in names {
+        refs.insert(name.clone(), Vec::new());
+    }
+    let known: HashSet<&str> = names.iter().map(String::as_str).collect();
+    // The ranges of the items do not overlap, so those sorted by start can be
+    // searched for the one containing a word.
+    let mut ranges: Vec<_> = name_to_range.iter().collect();
+    ranges.sort_by_key(|(_, range)| **range);
+
+    let mut i = 0;
+    let bytes = src.as_bytes();
+    while i < bytes.len() {
+        if bytes[i] == b'/' && i + 1 < bytes.len() {
+            if bytes[i + 1] == b'/' {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            } else if bytes[i + 1] == b'*' {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+        }
+
+        if bytes[i].is_ascii_alphabetic() || bytes[i] == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            let word = &src[start..i];
+
+            if known.contains(word) {
+                let end = ranges.partition_point(|(_, range)| range.0 <= start);
+                for (name, range) in ranges[..end].iter().rev() {
+                    if range.1 < start {
+                        break;
+                    }
+                    if word != name.as_str()
+                        && let Some(v) = refs.get_mut(*name)
+                        && !v.iter().any(|r| r == word)
+                    {
+                        v.push(word.to_string());
+                    }
+                }
+            }
+            continue;
+        }
+        i += 1;
+    }
+
+    refs
+}
+
+/// Returns the `// region` ... `// endregion` blocks among `items`, which may
+/// also be written `// #region` as some editors do. Nested regions belong to
+/// the outermost one, and markers without a partner are ignored.
+fn find_regions(items: &[Item], src: &str, line_starts: &LineStarts) -> Vec<Region> {
+    // The extent of each item without its comments, which are where the
+    // markers are found.
+    let cores: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let mut range = span_range(item.span(), src, line_starts);
+            for attr in item_attributes(item) {
+                range.start = range
+                    .start
+                    .min(span_range(attr.span(), src, line_starts).start);
+            }
+            range
+        })
+        .collect();
+
+    let mut regions: Vec<Region> = Vec::new();
+    let mut open = None;
+    let mut depth = 0;
+    for index in 0..=items.len() {
+        let gap = match (index.checked_sub(1), cores.get(index)) {
+            (None, Some(core)) => comment_lines_start(src, core.start)..core.start,
+            (Some(prev), Some(core)) => cores[prev].end..core.start,
+            (Some(prev), None) => cores[prev].end..comment_lines_end(src, cores[prev].end),
+            (None, None) => break,
+        };
+        for (marker, end, is_start) in region_markers(src, gap) {
+            if is_start {
+                if depth == 0 {
+                    open = Some((marker, index));
+                }
+                depth += 1;
+                continue;
+            }
+            if depth == 0 {
+                continue;
+            }
+            depth -= 1;
+            if depth == 0
+                && let Some((marker, first)) = open.take()
+                && first < index
+            {
+                // Comments directly above the marker belong to the first item
+                // and come along, but not past the previous item or region.
+                let mut limit = match first.checked_sub(1) {
+                    Some(prev) => item_range(&items[prev], src, line_starts).end,
+                    None => 0,
+                };
+                if let Some(prev) = regions.last() {
+                    limit = limit.max(prev.text.end);
+                }
+                let mut start = marker.min(item_range(&items[first], src, line_starts).start);
+                if start < limit {
+                    start = limit + (src[limit..].len() - src[limit..].trim_start().len());
+                }
+                regions.push(Region {
+                    items: first..=index - 1,
+                    text: start..end,
+                });
+            }
+        }
+    }
+    regions
+}
+
+fn fn_item_name(item: &Item) -> String {
+    match item {
+        Item::Fn(fn_item) => fn_item.sig.ident.unraw().to_string(),
+        _ => String::new(),
+    }
+}
+
+fn fn_visibility_rank(item: &Item) -> u8 {
+    match item {
+        Item::Fn(fn_item) => match &fn_item.vis {
+            syn::Visibility::Public(_) => 0,
+            syn::Visibility::Restricted(_) => 1,
+            syn::Visibility::Inherited => 2,
+        },
+        _ => 0,
+    }
+}
+
+fn has_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| is_cfg_test(&attr.meta))
+}
+
+/// Reports whether `attrs` tie the meaning of a type to the order of its
+/// fields or variants.
+fn has_order_dependent_layout(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let syn::Meta::List(list) = &attr.meta else {
+            return false;
+        };
+        let names: &[&str] = if list.path.is_ident("repr") {
+            &["C", "packed"]
+        } else if list.path.is_ident("derive") {
+            &["Ord", "PartialOrd"]
+        } else {
+            return false;
+        };
+        list.tokens.clone().into_iter().any(|token| {
+            matches!(token, proc_macro2::TokenTree::Ident(ident) if names.iter().any(|name| ident == name))
+        })
+    })
+}
+
+fn has_skip_directive(src: &str) -> bool {
+    src.lines()
+        .next()
+        .is_some_and(|line| line.trim() == "// reorder:skip")
+}
+
+/// Reassembles inner attributes, such as `//!` docs and `#![...]` crate
+/// attributes, in source order. Each attribute keeps its attached comments,
+/// and a blank line between two of them is kept as a single blank line.
+/// Comments between two attributes that are set apart from both, which can
+/// only come before the items, are kept where they were.
+fn header_to_string(
+    attrs: &[Attribute],
+    src: &str,
+    line_starts: &LineStarts,
+    indent: &str,
+) -> String {
+    // The whitespace around a comment collapses to a blank line, a line
+    // break or, within a line, a space.
+    let separator = |whitespace: &str| match whitespace.matches('\n').count() {
+        0 => " ",
+        1 => "\n",
+        _ => "\n\n",
+    };
+    let mut out = String::new();
+    let mut prev_end = None;
+
+    for attr in attrs {
+        let range = span_range(attr.span(), src, line_starts);
+        let start = leading_comment_start(src, range.start);
+        let end = trailing_comment_end(src, range.end);
+
+        if let Some(prev_end) = prev_end {
+            let gap = &src[prev_end..start.max(prev_end)];
+            let comments = gap.trim();
+            if comments.is_empty() {
+                out.push_str(if gap.matches('\n').count() > 1 {
+                    "\n\n"
+                } else {
+                    "\n"
+                });
+            } else {
+                out.push_str(separator(&gap[..gap.len() - gap.trim_start().len()]));
+                if out.ends_with('\n') {
+                    out.push_str(indent);
+                }
+                out.push_str(comments);
+                out.push_str(separator(&gap[gap.trim_end().len()..]));
+            }
+        }
+        if prev_end.is_none() || out.ends_with('\n') {
+            out.push_str(indent);
+        }
+        out.push_str(&src[start..end]);
+        prev_end = Some(end);
+    }
+
+    out
+}
+
+/// Returns the snippet of an impl block with its items sorted: associated
+/// constants and types first, then constructors, which take no `self` and
+/// return `Self`, then the other functions, each by visibility and then by
+/// name. Comments and attributes move with the item they are attached to,
+/// and the whitespace between items stays in place.
+///
+/// Returns `None` for impls with fewer than two items, already in order, or
+/// containing macro invocations, whose position may matter.
+fn impl_items_snippet(item: &Item, src: &str, line_starts: &LineStarts) -> Option<String> {
+    let Item::Impl(item_impl) = item else {
+        return None;
+    };
+    let self_name = impl_self_name(item);
+    let mut members = Vec::new();
+    for impl_item in &item_impl.items {
+        let (rank, vis, name, attrs) = match impl_item {
+            syn::ImplItem::Const(item) => (0, &item.vis, &item.ident, &item.attrs),
+            syn::ImplItem::Type(item) => (1, &item.vis, &item.ident, &item.attrs),
+            syn::ImplItem::Fn(item) => {
+                let sig = &item.sig;
+                let returns_self = match &sig.output {
+                    syn::ReturnType::Type(_, ty) => src[span_range(ty.span(), src, line_starts)]
+                        .split(|c: char| !c.is_alphanumeric() && c != '_')
+                        .any(|word| word == "Self" || self_name.as_deref() == Some(word)),
+                    syn::ReturnType::Default => false,
+                };
+                let constructor = sig.receiver().is_none() && returns_self;
+                (
+                    if constructor { 2 } else { 3 },
+                    &item.vis,
+                    &sig.ident,
+                    &item.attrs,
+                )
+            }
+            _ => return None,
+        };
+        let vis_rank = match vis {
+            syn::Visibility::Public(_) => 0,
+            syn::Visibility::Restricted(_) => 1,
+            syn::Visibility::Inherited => 2,
+        };
+        let mut start = span_range(impl_item.span(), src, line_starts).start;
+        for attr in attrs {
+            start = start.min(span_range(attr.span(), src, line_starts).start);
+        }
+        let end = span_range(impl_item.span(), src, line_starts).end;
+        members.push((
+            (rank, vis_rank, name.unraw().to_string()),
+            leading_comment_start(src, start)..trailing_comment_end(src, end),
+        ));
+    }
+    if members.len() < 2 {
+        return None;
+    }
+
+    let mut sorted: Vec<_> = members.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    if sorted.iter().zip(&members).all(|(a, b)| a.1 == b.1) {
+        return None;
+    }
+
+    let range = item_range(item, src, line_starts);
+    let mut text = src[range.start..members[0].1.start].to_string();
+    for (index, (_, member)) in sorted.iter().enumerate() {
+        if index > 0 {
+            text.push_str(&src[members[index - 1].1.end..members[index].1.start]);
+        }
+        text.push_str(&src[member.clone()]);
+    }
+    text.push_str(&src[members[members.len() - 1].1.end..range.end]);
+    Some(text.trim_end().to_string())
+}
+
+fn impl_self_name(item: &Item) -> Option<String> {
+    let Item::Impl(item_impl) = item else {
+        return None;
+    };
+    let mut ty = &*item_impl.self_ty;
+    while let syn::Type::Group(syn::TypeGroup { elem, .. })
+    | syn::Type::Paren(syn::TypeParen { elem, .. }) = ty
+    {
+        ty = elem;
+    }
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|s| s.ident.unraw().to_string()),
+        _ => None,
+    }
+}
+
+/// Returns whether `meta` is a `cfg` that requires `test`, either directly or
+/// applied through `cfg_attr`.
+fn is_cfg_test(meta: &syn::Meta) -> bool {
+    let syn::Meta::List(list) = meta else {
+        return false;
+    };
+    if list.path.is_ident("cfg") {
+        syn::parse2::<syn::Expr>(list.tokens.clone()).is_ok_and(|expr| contains_test(&expr))
+    } else if list.path.is_ident("cfg_attr") {
+        // Only the attributes a `cfg_attr` applies can make an item test-only;
+        // an item behind `cfg_attr(test, ...)` is still built outside tests.
+        list.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|metas| metas.iter().skip(1).any(is_cfg_test))
+    } else {
+        false
+    }
+}
+
+/// Returns whether an item carries `#[rustfmt::skip]` and so keeps its
+/// position.
+fn is_pinned(item: &Item) -> bool {
+    item_attributes(item).iter().any(|attr| {
+        let segments: Vec<String> = attr
+            .path()
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect();
+        segments == ["rustfmt", "skip"]
+    })
+}
+
+fn is_proc_macro(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        ["proc_macro", "proc_macro_attribute", "proc_macro_derive"]
+            .iter()
+            .any(|name| attr.path().is_ident(name))
+    })
+}
+
+fn is_public(vis: &syn::Visibility) -> bool {
+    match vis {
+        syn::Visibility::Public(_) => true,
+        syn::Visibility::Restricted(restricted) => !restricted.path.is_ident("self"),
+        syn::Visibility::Inherited => false,
+    }
+}
+
+fn is_std_crate(name: &str) -> bool {
+    name == "std"
+        || name == "core"
+        || name == "alloc"
+        || name.starts_with("std::")
+        || name.starts_with("core::")
+        || name.starts_with("alloc::")
+}
+
+/// Returns whether an item is a function marked `#[test]` or with a test
+/// attribute from a runtime crate, such as `#[tokio::test]`.
+fn is_test_fn(item: &Item) -> bool {
+    let Item::Fn(item_fn) = item else {
+        return false;
+    };
+    item_fn.attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "test")
+    })
+}
+
+fn is_test_module(item: &Item) -> bool {
+    match item {
+        Item::Mod(module) => has_cfg_test(&module.attrs),
+        _ => false,
+    }
+}
+
+fn item_attributes(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        Item::Verbatim(_) => &[],
+        _ => &[],
+    }
+}
+
+fn item_kind(item: &Item) -> &'static str {
+    match item {
+        Item::Const(_) => "Const",
+        Item::Enum(_) => "Enum",
+        Item::ExternCrate(_) => "ExternCrate",
+        Item::Fn(_) => "Fn",
+        Item::ForeignMod(_) => "ForeignMod",
+        Item::Impl(_) => "Impl",
+        Item::Macro(_) => "Macro",
+        Item::Mod(_) => "Mod",
+        Item::Static(_) => "Static",
+        Item::Struct(_) => "Struct",
+        Item::Trait(_) => "Trait",
+        Item::TraitAlias(_) => "TraitAlias",
+        Item::Type(_) => "Type",
+        Item::Union(_) => "Union",
+        Item::Use(_) => "Use",
+        _ => "Verbatim",
+    }
+}
+
+/// Returns the name of `item`, without the `r#` prefix of a raw identifier so
+/// that it sorts by the name it is spelled with.
+fn item_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Const(c) => Some(c.ident.unraw().to_string()),
+        Item::Enum(e) => Some(e.ident.unraw().to_string()),
+        Item::ExternCrate(e) => Some(e.ident.unraw().to_string()),
+        Item::Fn(f) => Some(f.sig.ident.unraw().to_string()),
+        Item::Macro(m) => m.ident.as_ref().map(|ident| ident.unraw().to_string()),
+        Item::Mod(m) => Some(m.ident.unraw().to_string()),
+        Item::Static(s) => Some(s.ident.unraw().to_string()),
+        Item::Struct(s) => Some(s.ident.unraw().to_string()),
+        Item::Trait(t) => Some(t.ident.unraw().to_string()),
+        Item::TraitAlias(t) => Some(t.ident.unraw().to_string()),
+        Item::Type(t) => Some(t.ident.unraw().to_string()),
+        Item::Union(u) => Some(u.ident.unraw().to_string()),
+        _ => None,
+    }
+}
+
+fn item_range(item: &Item, src: &str, line_starts: &LineStarts) -> std::ops::Range<usize> {
+    let mut range = span_range(item.span(), src, line_starts);
+
+    for attr in item_attributes(item) {
+        let attr_range = span_range(attr.span(), src, line_starts);
+        if attr_range.start < range.start {
+            range.start = attr_range.start;
+        }
+    }
+
+    range.start = leading_comment_start(src, range.start.min(range.end));
+    range.end = trailing_comment_end(src, range.end);
+    range
+}
+
+fn item_snippet(item: &Item, src: &str, line_starts: &LineStarts) -> String {
+    src[item_range(item, src, line_starts)]
+        .trim_end()
+        .to_string()
+}
+
+fn item_visibility(item: &Item) -> Option<&syn::Visibility> {
+    match item {
+        Item::Const(item) => Some(&item.vis),
+        Item::Enum(item) => Some(&item.vis),
+        Item::ExternCrate(item) => Some(&item.vis),
+        Item::Fn(item) => Some(&item.vis),
+        Item::Mod(item) => Some(&item.vis),
+        Item::Static(item) => Some(&item.vis),
+        Item::Struct(item) => Some(&item.vis),
+        Item::Trait(item) => Some(&item.vis),
+        Item::TraitAlias(item) => Some(&item.vis),
+        Item::Type(item) => Some(&item.vis),
+        Item::Union(item) => Some(&item.vis),
+        Item::Use(item) => Some(&item.vis),
+        _ => None,
+    }
+}
+
+fn leading_comment_start(src: &str, start: usize) -> usize {
+    // Only the blanks before `start` are scanned, so that items sharing a long
+    // line do not each walk back to its start.
+    let before = src[..start].trim_end_matches(|c: char| c.is_whitespace() && c != '\n');
+    if !before.is_empty() && !before.ends_with('\n') {
+        return start;
+    }
+    let line_start = before.len();
+
+    let mut start = start;
+    let mut cursor = line_start;
+    while cursor > 0 {
+        let prev_start = src[..cursor - 1].rfind('\n').map_or(0, |i| i + 1);
+        let raw_line = &src[prev_start..cursor - 1];
+        let line = raw_line.trim();
+        let is_doc =
+            (line.starts_with("///") && !line.starts_with("////")) || line.starts_with("//!");
+        if !line.starts_with("//") || is_doc {
+            break;
+        }
+        start = prev_start + (raw_line.len() - raw_line.trim_start().len());
+        cursor = prev_start;
+    }
+
+    start
+}
+
+fn line_indent(src: &str, pos: usize) -> &str {
+    let before = src[..pos].trim_end_matches(|c: char| c.is_whitespace() && c != '\n');
+    if before.is_empty() || before.ends_with('\n') {
+        &src[before.len()..pos]
+    } else {
+        ""
+    }
+}
+
+fn line_start_offsets(src: &str) -> LineStarts {
+    let mut starts = Vec::with_capacity(src.len() / 32 + 2);
+    let mut ascii_lengths = Vec::with_capacity(starts.capacity());
+    let mut checkpoints = Vec::new();
+    let mut first_checkpoints = Vec::with_capacity(starts.capacity());
+    starts.push(0);
+    first_checkpoints.push(0);
+    // The characters seen past the leading ASCII bytes of the current line.
+    let mut chars: Option<usize> = None;
+    for (idx, c) in src.char_indices() {
+        if c == '\n' {
+            if chars.is_none() {
+                ascii_lengths.push(idx - starts[starts.len() - 1]);
+            }
+            starts.push(idx + 1);
+            first_checkpoints.push(checkpoints.len());
+            chars = None;
+            continue;
+        }
+        let count = match &mut chars {
+            Some(count) => count,
+            None if c.is_ascii() => continue,
+            None => {
+                ascii_lengths.push(idx - starts[starts.len() - 1]);
+                chars.insert(0)
+            }
+        };
+        if *count % CHECKPOINT_CHARS == 0 {
+            checkpoints.push(idx);
+        }
+        *count += 1;
+    }
+    if chars.is_none() {
+        ascii_lengths.push(src.len() - starts[starts.len() - 1]);
+    }
+    if *starts.last().unwrap_or(&0) != src.len() {
+        starts.push(src.len());
+        ascii_lengths.push(0);
+        first_checkpoints.push(checkpoints.len());
+    }
+    LineStarts {
+        ascii_lengths,
+        checkpoints,
+        first_checkpoints,
+        starts,
+    }
+}
+
+fn module_snippet(
+    item_mod: &syn::ItemMod,
+    src: &str,
+    line_starts: &LineStarts,
+    config: &Config,
+) -> String {
+    let item = Item::Mod(item_mod.clone());
+    let range = item_range(&item, src, line_starts);
+    let Some((brace, items)) = &item_mod.content else {
+        return item_snippet(&item, src, line_starts);
+    };
+    let Some(first) = items.first() else {
+        return item_snippet(&item, src, line_starts);
+    };
+
+    let open = span_range(brace.span.open(), src, line_starts).end;
+    let close = span_range(brace.span.close(), src, line_starts).start;
+    let first_start = item_range(first, src, line_starts).start;
+    let indent = line_indent(src, first_start);
+
+    let inner_attrs: Vec<Attribute> = item_mod
+        .attrs
+        .iter()
+        .filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_)))
+        .cloned()
+        .collect();
+
+    let mut text = src[range.start..open].to_string();
+    text.push('\n');
+    if !inner_attrs.is_empty() {
+        text.push_str(header_to_string(&inner_attrs, src, line_starts, indent).trim_end());
+        text.push_str("\n\n");
+    }
+    let (body, _) = reorder_items(items.clone(), src, line_starts, config, indent);
+    text.push_str(&body);
+    text.push_str(line_indent(src, close));
+    text.push_str(&src[close..range.end]);
+    text
+}
+
+fn partition_public_first(bucket: &mut Vec<Entry>) {
+    let slots: Vec<usize> = (0..bucket.len())
+        .filter(|&i| bucket[i].public.is_some())
+        .collect();
+    let mut entries: Vec<Option<Entry>> = std::mem::take(bucket).into_iter().map(Some).collect();
+
+    let mut ranked: Vec<Entry> = slots.iter().filter_map(|&i| entries[i].take()).collect();
+    ranked.sort_by_key(|entry| entry.public != Some(true));
+    for (&slot, entry) in slots.iter().zip(ranked) {
+        entries[slot] = Some(entry);
+    }
+
+    *bucket = entries.into_iter().flatten().collect();
+}
+
+/// Returns the index just past the raw string literal, such as `r#"..."#` or
+/// `br"..."`, that starts at `start`, if one does.
+fn raw_string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let prefix = match &bytes[start..] {
+        [b'r', ..] => 1,
+        [b'b' | b'c', b'r', ..] => 2,
+        _ => return None,
+    };
+    let hashes = bytes[start + prefix..]
+        .iter()
+        .take_while(|&&b| b == b'#')
+        .count();
+    let body = start + prefix + hashes;
+    if bytes.get(body) != Some(&b'"') {
+        return None;
+    }
+
+    let mut closing = vec![b'"'];
+    closing.extend(std::iter::repeat_n(b'#', hashes));
+    let end = bytes[body + 1..]
+        .windows(closing.len())
+        .position(|window| window == closing.as_slice())
+        .map_or(bytes.len(), |offset| body + 1 + offset + closing.len());
+    Some(end)
+}
+
+/// Returns the region markers on their own lines within `range` of `src`, as
+/// the offset of the marker, the end of its line and whether it opens a region.
+fn region_markers(src: &str, range: std::ops::Range<usize>) -> Vec<(usize, usize, bool)> {
+    let starts_with_word = |text: &str, word: &str| {
+        text.strip_prefix(word)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    };
+
+    let mut markers = Vec::new();
+    let mut pos = range.start;
+    for line in src[range].split_inclusive('\n') {
+        let line_start = pos;
+        pos += line.len();
+        let text = line.trim_start();
+        let before = &src[src[..line_start].rfind('\n').map_or(0, |i| i + 1)..line_start];
+        let Some(comment) = text.strip_prefix("//") else {
+            continue;
+        };
+        if !before.trim().is_empty() {
+            continue;
+        }
+        let word = comment.trim_start();
+        let word = word.strip_prefix('#').unwrap_or(word);
+        let opens = if starts_with_word(word, "region") {
+            true
+        } else if starts_with_word(word, "endregion") {
+            false
+        } else {
+            continue;
+        };
+        let marker = line_start + (line.len() - text.len());
+        markers.push((marker, line_start + line.trim_end().len(), opens));
+    }
+    markers
+}
+
+/// Returns the index among `attrs` of a `#![reorder(category_order = "...")]`
+/// attribute and the category order it gives. The attribute may also be
+/// applied through `cfg_attr`, which keeps rustc from rejecting it.
+fn reorder_attribute(attrs: &[Attribute]) -> Result<Option<(usize, Vec<Category>)>> {
+    for (index, attr) in attrs.iter().enumerate() {
+        let Some(list) = reorder_attribute_list(&attr.meta) else {
+            continue;
+        };
+        let args = list
+            .parse_args_with(Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated)
+            .map_err(|err| anyhow!("invalid `#![reorder]` attribute: {err}"))?;
+        let mut order = None;
+        for arg in args {
+            if !arg.path.is_ident("category_order") {
+                let name: Vec<_> = arg
+                    .path
+                    .segments
+                    .iter()
+                    .map(|s| s.ident.to_string())
+                    .collect();
+                bail!("unknown `#![reorder]` option `{}`", name.join("::"));
+            }
+            let syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) = &arg.value
+            else {
+                bail!("`category_order` in `#![reorder]` must be a string");
+            };
+            order = Some(
+                parse_category_list(&lit.value())
+                    .context("invalid `category_order` in `#![reorder]`")?,
+            );
+        }
+        if let Some(order) = order {
+            return Ok(Some((index, order)));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the arguments of `reorder(...)` in `meta`, looking inside
+/// `cfg_attr`.
+fn reorder_attribute_list(meta: &syn::Meta) -> Option<syn::MetaList> {
+    let syn::Meta::List(list) = meta else {
+        return None;
+    };
+    if list.path.is_ident("reorder") {
+        return Some(list.clone());
+    }
+    if !list.path.is_ident("cfg_attr") {
+        return None;
+    }
+    list.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)
+        .ok()?
+        .iter()
+        .skip(1)
+        .find_map(reorder_attribute_list)
+}
+
+fn reorder_items(
+    items: Vec<Item>,
+    src: &str,
+    line_starts: &LineStarts,
+    config: &Config,
+    indent: &str,
+) -> (String, Vec<ItemMove>) {
+    let use_runs = if config.preserve_use_groups {
+        use_runs(&items, src, line_starts)
+    } else {
+        HashMap::new()
+    };
+    let regions = if config.preserve_regions {
+        find_regions(&items, src, line_starts)
+    } else {
+        Vec::new()
+    };
+    let (pinned, mut items): (Vec<_>, Vec<_>) =
+        items.into_iter().enumerate().partition(|(_, item)| {
+            is_pinned(item)
+                || config.spacing_only
+                || !(config.only.is_empty() || config.only.contains(&section(item, config)))
+        });
+
+    // A region is emitted verbatim as a single entry, so its leader carries
+    // the text of the whole region and the other items are dropped.
+    let mut region_members: HashMap<usize, Vec<(usize, ItemMove)>> = HashMap::new();
+    let mut region_texts: HashMap<usize, std::ops::Range<usize>> = HashMap::new();
+    let mut region_ends: HashMap<usize, usize> = HashMap::new();
+    for region in regions {
+        if pinned.iter().any(|(index, _)| region.items.contains(index)) {
+            continue;
+        }
+        let mut members = Vec::new();
+        let mut leader = None;
+        items.retain(|(index, item)| {
+            let range = item_range(item, src, line_starts);
+            if *index == region.items.end() + 1 && range.start < region.text.end {
+                region_ends.insert(range.start, region.text.end);
+            }
+            if !region.items.contains(index) {
+                return true;
+            }
+            let offset = range.start.max(region.text.start) - region.text.start;
+            members.push((
+                offset,
+                ItemMove {
+                    category: category(item),
+                    name: item_name(item),
+                    new_line: 0,
+                    original_line: line_starts
+                        .partition_point(|&line_start| line_start <= range.start),
+                    unmatched_self_type: None,
+                },
+            ));
+            leader.get_or_insert(range.start) == &range.start
+        });
+        if let Some(start) = leader {
+            region_members.insert(start, members);
+            region_texts.insert(start, region.text);
+        }
+    }
+
+    let mut followers = if config.preserve_cfg_groups {
+        split_cfg_runs(&mut items, src, line_starts)
+    } else {
+        HashMap::new()
+    };
+    let snippet = |item: &Item| {
+        let start = item_range(item, src, line_starts).start;
+        if let Some(text) = region_texts.get(&start) {
+            return src[text.clone()].to_string();
+        }
+        let mut text = match item {
+            Item::Mod(item_mod) if !is_test_module(item) => {
+                module_snippet(item_mod, src, line_starts, config)
+            }
+            Item::Struct(_) | Item::Enum(_) if config.sort_fields => {
+                fields_snippet(item, src, line_starts)
+                    .unwrap_or_else(|| item_snippet(item, src, line_starts))
+            }
+            Item::Impl(_) if config.sort_impl_items => impl_items_snippet(item, src, line_starts)
+                .unwrap_or_else(|| item_snippet(item, src, line_starts)),
+            _ => item_snippet(item, src, line_starts),
+        };
+        // The `// endregion` marker of a preceding region stays with it.
+        if let Some(&end) = region_ends.get(&start) {
+            text = text[end - start..].trim_start().to_string();
+        }
+        if config.collapse_blank_lines {
+            collapse_header_blank_lines(item, src, line_starts, text)
+        } else {
+            text
+        }
+    };
+
+    let new_entry = |item: &Item, text: String| {
+        let start = item_range(item, src, line_starts).start;
+        let (group, use_path) = match item {
+            Item::Use(use_item) => match use_runs.get(&start) {
+                Some(&run) => (run, use_path(&use_item.tree)),
+                None => (use_group(use_item), use_path(&use_item.tree)),
+            },
+            _ => (0, Vec::new()),
+        };
+        let first = match region_ends.get(&start) {
+            Some(&end) => end + (src[end..].len() - src[end..].trim_start().len()),
+            None => start,
+        };
+        Entry {
+            attached: Vec::new(),
+            category: category(item),
+            group,
+            line: line_starts.partition_point(|&line_start| line_start <= first),
+            members: Vec::new(),
+            name: item_name(item),
+            public: item_visibility(item).map(is_public),
+            self_type: impl_self_name(item),
+            text,
+            unmatched: false,
+            use_path,
+        }
+    };
+
+    let (struct_enum_items, rest_items): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .map(|(_, item)| item)
+        .partition(|item| matches!(item, Item::Struct(_) | Item::Enum(_) | Item::Union(_)));
+
+    let (fn_items, other_items): (Vec<_>, Vec<_>) = rest_items
+        .into_iter()
+        .partition(|item| matches!(item, Item::Fn(_)));
+
+    let sorted_struct_enums = sort_by_usage(struct_enum_items, src, line_starts);
+
+    let mut sorted_fn_items = fn_items;
+    sorted_fn_items.sort_by(|a, b| {
+        fn_visibility_rank(a)
+            .cmp(&fn_visibility_rank(b))
+            .then_with(|| fn_item_name(a).cmp(&fn_item_name(b)))
+    });
+
+    let mut type_order: Vec<String> = sorted_struct_enums.iter().filter_map(item_name).collect();
+    if config.sort_within {
+        type_order.sort();
+    }
+
+    let order = config.resolved_order();
+    let mut buckets: Vec<Vec<Entry>> = order.iter().map(|_| Vec::new()).collect();
+    for item in other_items
+        .into_iter()
+        .chain(sorted_struct_enums)
+        .chain(sorted_fn_items)
+    {
+        let cat = section(&item, config);
+        let slot = order
+            .iter()
+            .position(|c| *c == cat)
+            .expect("category order covers every category");
+        let mut entry = new_entry(&item, snippet(&item));
+        if let Some(members) = region_members.remove(&item_range(&item, src, line_starts).start) {
+            entry.members = members;
+        }
+        if let Some(items) = followers.remove(&item_range(&item, src, line_starts).start) {
+            entry.attached = items
+                .iter()
+                .map(|item| new_entry(item, snippet(item)))
+                .collect();
+        }
+        buckets[slot].push(entry);
+    }
+
+    if config.impls_with_types {
+        attach_impls(&order, &mut buckets);
+    }
+
+    let mut type_positions: HashMap<&String, usize> = HashMap::new();
+    for (position, name) in type_order.iter().enumerate() {
+        type_positions.entry(name).or_insert(position);
+    }
+
+    let mut sequence: Vec<(Option<Category>, Entry)> = Vec::new();
+    for (&cat, mut bucket) in order.iter().zip(buckets) {
+        if bucket.is_empty() {
+            continue;
+        }
+
+        match cat {
+            Category::Impl => bucket.sort_by(|a, b| {
+                let type_pos = |e: &Entry| type_positions.get(e.self_type.as_ref()?).copied();
+                // The sort is stable, so impls of the same type keep their
+                // source order.
+                match (type_pos(a), type_pos(b)) {
+                    (Some(i), Some(j)) => i.cmp(&j),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) if config.sort_within => match (&a.self_type, &b.self_type) {
+                        (Some(a), Some(b)) => a.cmp(b),
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        (None, Some(_)) => std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    },
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            }),
+            // Type aliases sit among the constants, so sort by name rather
+            // than by the keyword the text starts with.
+            Category::Const if config.types_with_consts => bucket.sort_by(|a, b| {
+                a.name
+                    .cmp(&b.name)
+                    .then_with(|| snippet_sort_key(&a.text).cmp(snippet_sort_key(&b.text)))
+            }),
+            _ if config.sort_imports && cat.is_use() => bucket.sort_by(|a, b| {
+                a.group
+                    .cmp(&b.group)
+                    .then_with(|| a.use_path.cmp(&b.use_path))
+                    .then_with(|| snippet_sort_key(&a.text).cmp(snippet_sort_key(&b.text)))
+            }),
+            _ if config.sort_within && !cat.is_use() => {
+                bucket.sort_by(|a, b| match (&a.name, &b.name) {
+                    (Some(a), Some(b)) => a.cmp(b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                })
+            }
+            Category::Typedef
+            | Category::Union
+            | Category::Foreign
+            | Category::ProcMacro
+            | Category::Fn
+            | Category::AsyncFn
+            | Category::Tests => {}
+            _ => bucket.sort_by(|a, b| {
+                a.group
+                    .cmp(&b.group)
+                    .then_with(|| snippet_sort_key(&a.text).cmp(snippet_sort_key(&b.text)))
+            }),
+        }
+
+        if config.pub_first && !cat.is_use() {
+            partition_public_first(&mut bucket);
+        }
+
+        sequence.extend(bucket.into_iter().map(|entry| (Some(cat), entry)));
+    }
+
+    // Pinned items, and those outside the categories given by `only` or every
+    // item with `spacing_only`, go back to their original index among the
+    // items, with the reordered items flowing around them. The pinned items
+    // are in source order, so they are merged in a single pass.
+    let mut reordered = std::mem::take(&mut sequence).into_iter();
+    for (index, item) in pinned {
+        while sequence.len() < index
+            && let Some(entry) = reordered.next()
+        {
+            sequence.push(entry);
+        }
+        // Items outside `only` keep their section for spacing, so adjacent
+        // ones are spaced as if they had been reordered.
+        let (cat, text) = if is_pinned(&item) {
+            (None, item_snippet(&item, src, line_starts))
+        } else {
+            (Some(section(&item, config)), snippet(&item))
+        };
+        let entry = new_entry(&item, text);
+        sequence.push((cat, entry));
+    }
+    sequence.extend(reordered);
+
+    let mut out = String::new();
+    let mut moves = Vec::new();
+    let mut prev: Option<(Option<Category>, usize)> = None;
+    for (cat, entry) in sequence {
+        if let Some((prev_cat, prev_group)) = prev {
+            let blanks = match cat {
+                Some(cat) if prev_cat == Some(cat) && prev_group == entry.group => {
+                    config.blank_lines_within(cat)
+                }
+                Some(cat) if prev_cat == Some(cat) => 1,
+                _ => config.blank_lines_between,
+            };
+            out.push_str(&"\n".repeat(blanks));
+        }
+        prev = Some((cat, entry.group));
+        emit_entry(entry, &mut out, &mut moves, config, indent);
+    }
+
+    let out_line_starts = line_start_offsets(&out);
+    let moves = moves
+        .into_iter()
+        .map(|(offset, item_move)| ItemMove {
+            new_line: out_line_starts.partition_point(|&start| start <= offset),
+            ..item_move
+        })
+        .collect();
+    (out, moves)
+}
+
+/// Reorders the items of `src` within `lines`, which is free of a byte order
+/// mark and uses LF line endings.
+fn reorder_lines(src: &str, config: &Config, lines: &RangeInclusive<usize>) -> Result<String> {
+    let file: File = syn::parse_file(src)?;
+    let file_config = with_file_order(&file.attrs, config)?;
+    let config = file_config.as_ref().unwrap_or(config);
+    let line_starts = line_start_offsets(src);
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+
+    let (mut start, mut end) = (src.len(), 0);
+    let selected: Vec<Item> = file
+        .items
+        .into_iter()
+        .filter(|item| {
+            let range = item_range(item, src, &line_starts);
+            let inside = lines.contains(&line_of(range.start))
+                && lines.contains(&line_of(range.end.saturating_sub(1).max(range.start)));
+            if inside {
+                start = start.min(range.start);
+                end = end.max(range.end);
+            }
+            inside
+        })
+        .collect();
+    if selected.len() < 2 {
+        return Ok(src.to_string());
+    }
+
+    let (body, _) = reorder_items(selected, src, &line_starts, config, "");
+    Ok(format!(
+        "{}{}{}",
+        &src[..start],
+        body.trim_end_matches('\n'),
+        &src[end..]
+    ))
+}
+
+fn reorder_normalized(src: &str, config: &Config) -> Result<String> {
+    let (out, _) = reorder_pass(src, config)?;
+    // Reordering already ordered output must be a no-op, or `--check` would
+    // flag freshly reordered files. The exception is a stripped `#![reorder]`
+    // attribute, which no longer applies to the output.
+    if !config.strip_reorder_attribute {
+        debug_assert_eq!(
+            reorder_pass(&out, config)
+                .ok()
+                .map(|(out, _)| out)
+                .as_deref(),
+            Some(out.as_str()),
+            "reordering is not idempotent"
+        );
+    }
+    Ok(out)
+}
+
+/// Reorders the longest prefix of `src` that parses and ends at the start of
+/// an unindented line before the parse error `err`, and appends the rest of
+/// `src` verbatim. Fails with `err` if no such prefix holds any items.
+fn reorder_partial(src: &str, config: &Config, err: syn::Error) -> Result<String> {
+    let line_starts = line_start_offsets(src);
+    // Errors at the end of the input have no location.
+    let error_line = match err.span().start().line {
+        0 => line_starts.len(),
+        line => line.min(line_starts.len()),
+    };
+
+    for &cut in line_starts[1..error_line].iter().rev() {
+        if src[cut..].starts_with(char::is_whitespace) {
+            continue;
+        }
+        let prefix = &src[..cut];
+        match syn::parse_file(prefix) {
+            Ok(file) if file.items.is_empty() => break,
+            Ok(_) => {
+                let out = reorder_normalized(prefix, config)?;
+                let gap = &prefix[prefix.trim_end().len()..];
+                return Ok(format!("{}{gap}{}", out.trim_end(), &src[cut..]));
+            }
+            Err(_) => {}
+        }
+    }
+    Err(err.into())
+}
+
+/// Reorders `src` and reports where each top-level item was placed.
+fn reorder_pass(src: &str, config: &Config) -> Result<(String, Vec<ItemMove>)> {
+    let mut file: File = syn::parse_file(src)?;
+    let file_config = with_file_order(&file.attrs, config)?;
+    let config = file_config.as_ref().unwrap_or(config);
+    if config.strip_reorder_attribute
+        && let Some((index, _)) = reorder_attribute(&file.attrs)?
+    {
+        file.attrs.remove(index);
+    }
+    // Without items there is nothing to reorder, and comments that are not
+    // attached to an item would otherwise be lost.
+    if file.items.is_empty() {
+        return Ok((src.to_string(), Vec::new()));
+    }
+    let line_starts = line_start_offsets(src);
+
+    let shebang = file.shebang.take();
+    let crate_attrs = std::mem::take(&mut file.attrs);
+
+    let (body, mut moves) = reorder_items(file.items, src, &line_starts, config, "");
+
+    let mut out = String::new();
+    if let Some(sb) = shebang {
+        out.push_str(&sb);
+        out.push('\n');
+    }
+    if !crate_attrs.is_empty() {
+        let header = header_to_string(&crate_attrs, src, &line_starts, "");
+        out.push_str(header.trim_end());
+        out.push_str("\n\n");
+    }
+
+    if !out.is_empty() && !body.is_empty() {
+        while !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+    let header_lines = out.matches('\n').count();
+    for item_move in &mut moves {
+        item_move.new_line += header_lines;
+    }
+    out.push_str(&body);
+
+    while out.ends_with("\n\n\n") {
+        out.pop();
+    }
+    let src_has_trailing_newline = src.ends_with('\n');
+    let out_has_trailing_newline = out.ends_with('\n');
+    if src_has_trailing_newline && !out_has_trailing_newline {
+        out.push('\n');
+    } else if !src_has_trailing_newline && out_has_trailing_newline {
+        out.pop();
+    }
+
+    Ok((out, moves))
+}
+
+/// Applies `reorder` to `src` with any byte order mark removed and line
+/// endings normalized to LF, then restores both, using the line ending and
+/// final newline of `config` where it sets them. Skipped and generated
+/// sources are returned unchanged.
+fn reorder_text(
+    src: &str,
+    config: &Config,
+    reorder: &dyn Fn(&str) -> Result<String>,
+) -> Result<String> {
+    if let Some(rest) = src.strip_prefix('\u{feff}') {
+        return Ok(format!("\u{feff}{}", reorder_text(rest, config, reorder)?));
+    }
+
+    if has_skip_directive(src) || is_generated(src, config) {
+        return Ok(src.to_string());
+    }
+
+    let crlf = match config.line_ending {
+        Some(line_ending) => line_ending == LineEnding::Crlf,
+        None => uses_crlf(src),
+    };
+    let mut out = reorder(&src.replace("\r\n", "\n"))?;
+    match config.final_newline {
+        Some(true) if !out.is_empty() && !out.ends_with('\n') => out.push('\n'),
+        Some(false) => out.truncate(out.trim_end_matches('\n').len()),
+        _ => {}
+    }
+    if crlf {
+        out = out.replace('\n', "\r\n");
+    }
+    Ok(out)
+}
+
+/// Returns the section `item` is placed in under `config`: type aliases join
+/// constants with `types_with_consts`, while statics, trait aliases and
+/// `async fn` functions leave their section for the `static`, `static_mut`,
+/// `trait_alias` and `async_fn` sections when those are in the category order.
+fn section(item: &Item, config: &Config) -> Category {
+    let listed = |category| config.category_order.contains(&category);
+    match item {
+        Item::Type(_) if config.types_with_consts && category(item) == Category::Type => {
+            Category::Const
+        }
+        Item::Static(item_static) if category(item) == Category::Const => {
+            let mutable = matches!(item_static.mutability, syn::StaticMutability::Mut(_));
+            if mutable && listed(Category::StaticMut) {
+                Category::StaticMut
+            } else if listed(Category::Static) {
+                Category::Static
+            } else {
+                Category::Const
+            }
+        }
+        Item::TraitAlias(_)
+            if category(item) == Category::Trait && listed(Category::TraitAlias) =>
+        {
+            Category::TraitAlias
+        }
+        Item::Fn(item_fn)
+            if item_fn.sig.asyncness.is_some()
+                && category(item) == Category::Fn
+                && listed(Category::AsyncFn) =>
+        {
+            Category::AsyncFn
+        }
+        _ => category(item),
+    }
+}
+
+fn snippet_sort_key(snippet: &str) -> &str {
+    let mut rest = snippet;
+    while rest.trim_start().starts_with("//") {
+        match rest.find('\n') {
+            Some(i) => rest = &rest[i + 1..],
+            None => break,
+        }
+    }
+    rest.trim_start()
+}
+
+fn sort_by_usage(items: Vec<Item>, src: &str, line_starts: &LineStarts) -> Vec<Item> {
+    if items.is_empty() {
+        return items;
+    }
+
+    let mut name_to_item: HashMap<String, Item> = HashMap::new();
+    let mut name_to_range: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut names: Vec<String> = Vec::new();
+
+    for item in &items {
+        let name = item_name(item);
+        if let Some(n) = name {
+            // Use the item's own span rather than searching the source, which
+            // could find an `impl` block and make the result depend on layout.
+            let range = span_range(item.span(), src, line_starts);
+            name_to_range.insert(n.clone(), (range.start, range.end));
+            name_to_item.insert(n.clone(), item.clone());
+            names.push(n);
+        }
+    }
+
+    let refs = find_references(&names, &name_to_range, src);
+
+    let mut outgoing_counts: HashMap<String, usize> = HashMap::new();
+    for name in &names {
+        outgoing_counts.insert(name.clone(), refs.get(name).map(|v| v.len()).unwrap_or(0));
+    }
+
+    let mut name_count: Vec<(String, usize)> = names
+        .iter()
+        .map(|n| (n.clone(), *outgoing_counts.get(n).unwrap_or(&0)))
+        .collect();
+
+    name_count.sort_by_key(|b| std::cmp::Reverse(b.1));
+
+    let mut sorted: Vec<Item> = Vec::new();
+    for (name, _) in name_count {
+        if let Some(item) = name_to_item.get(&name) {
+            sorted.push(item.clone());
+        }
+    }
+
+    sorted
+}
+
+fn span_range(
+    span: proc_macro2::Span,
+    src: &str,
+    line_starts: &LineStarts,
+) -> std::ops::Range<usize> {
+    // Columns count characters, so only the part of a line past its leading
+    // ASCII bytes needs to be walked, and that from the nearest checkpoint,
+    // which keeps long lines cheap.
+    let to_byte = |location: proc_macro2::LineColumn| {
+        let line_index = location.line.saturating_sub(1);
+        let Some(&line_base) = line_starts.get(line_index) else {
+            return src.len();
+        };
+        let ascii_length = line_starts.ascii_lengths[line_index];
+        if location.column <= ascii_length {
+            return line_base + location.column;
+        }
+        let column = location.column - ascii_length;
+        let first = line_starts.first_checkpoints[line_index];
+        let count = line_starts
+            .first_checkpoints
+            .get(line_index + 1)
+            .map_or(line_starts.checkpoints.len(), |&next| next)
+            - first;
+        if count == 0 {
+            return column_to_byte(src, line_base + ascii_length, column);
+        }
+        let checkpoint = (column / CHECKPOINT_CHARS).min(count - 1);
+        column_to_byte(
+            src,
+            line_starts.checkpoints[first + checkpoint],
+            column - checkpoint * CHECKPOINT_CHARS,
+        )
+    };
+
+    let mut start_idx = to_byte(span.start());
+    let end_idx = to_byte(span.end());
+
+    if start_idx > end_idx {
+        start_idx = end_idx;
+    }
+
+    start_idx..end_idx
+}
+
+/// Splits off runs of adjacent items gated by the same `#[cfg(...)]`
+/// predicates. The first item of each run is kept in `items`, and the others
+/// are returned in source order keyed by the start offset of that first item.
+fn split_cfg_runs(
+    items: &mut Vec<(usize, Item)>,
+    src: &str,
+    line_starts: &LineStarts,
+) -> HashMap<usize, Vec<Item>> {
+    let mut followers: HashMap<usize, Vec<Item>> = HashMap::new();
+    let mut run: Option<(usize, usize, Vec<String>)> = None;
+
+    for (index, item) in std::mem::take(items) {
+        let predicates = cfg_predicates(item_attributes(&item));
+        if let Some((last, start, run_predicates)) = &mut run
+            && *last + 1 == index
+            && !predicates.is_empty()
+            && predicates == *run_predicates
+        {
+            *last = index;
+            followers.entry(*start).or_default().push(item);
+            continue;
+        }
+        run = Some((index, item_range(&item, src, line_starts).start, predicates));
+        items.push((index, item));
+    }
+
+    followers
+}
+
+/// Returns the index just past the `"` that closes a string literal whose
+/// contents start at `start`.
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the end of the comments that follow `end` on its line. A line
+/// comment runs to the end of the line, and block comments, which may be
+/// followed by more, to their closing `*/`. Doc comments belong to the next
+/// item and are left to it.
+fn trailing_comment_end(src: &str, end: usize) -> usize {
+    let mut end = end;
+    loop {
+        let rest = src[end..].trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        let start = src.len() - rest.len();
+        if rest.starts_with("//") {
+            return rest.find('\n').map_or(src.len(), |i| start + i);
+        }
+        let is_doc =
+            (rest.starts_with("/**") && !rest.starts_with("/**/")) || rest.starts_with("/*!");
+        if !rest.starts_with("/*") || is_doc {
+            return end;
+        }
+        end = block_comment_end(src.as_bytes(), start);
+    }
+}
+
+/// Removes the blank lines at the start of `text` and all but the last
+/// newline at its end.
+fn trim_blank_lines(text: &str) -> String {
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            break;
+        }
+        start += line.len();
+    }
+    let text = &text[start..];
+    let end = text.trim_end().len();
+    if end < text.len() && text[end..].contains('\n') {
+        format!("{}\n", &text[..end])
+    } else {
+        text.to_string()
+    }
+}
+
+fn use_group(use_item: &syn::ItemUse) -> usize {
+    fn get_first_ident(tree: &syn::UseTree) -> Option<&syn::Ident> {
+        match tree {
+            syn::UseTree::Path(tree) => Some(&tree.ident),
+            syn::UseTree::Group(tree) => tree.items.first().and_then(|t| get_first_ident(t)),
+            syn::UseTree::Name(tree) => Some(&tree.ident),
+            syn::UseTree::Rename(_) | syn::UseTree::Glob(_) => None,
+        }
+    }
+
+    let ident = match get_first_ident(&use_item.tree) {
+        Some(id) => id,
+        _ => return 1,
+    };
+    let ident_str = ident.to_string();
+    if matches!(ident_str.as_str(), "crate" | "self" | "super") {
+        return 2;
+    }
+    if is_std_crate(&ident_str) {
+        return 0;
+    }
+    1
+}
+
+/// Returns the path segments of a `use` tree up to any `{...}` group, so a
+/// grouped import sorts by its common prefix.
+fn use_path(tree: &syn::UseTree) -> Vec<String> {
+    let mut path = Vec::new();
+    let mut tree = tree;
+    loop {
+        match tree {
+            syn::UseTree::Path(use_path) => {
+                path.push(use_path.ident.unraw().to_string());
+                tree = &use_path.tree;
+            }
+            syn::UseTree::Name(name) => {
+                path.push(name.ident.unraw().to_string());
+                return path;
+            }
+            syn::UseTree::Rename(rename) => {
+                path.push(rename.ident.unraw().to_string());
+                return path;
+            }
+            syn::UseTree::Glob(_) => {
+                path.push("*".to_string());
+                return path;
+            }
+            syn::UseTree::Group(_) => return path,
+        }
+    }
+}
+
+/// Numbers the runs of consecutive `use` declarations in `items` that are
+/// separated by blank lines or other items, keyed by the start offset of each
+/// declaration.
+fn use_runs(items: &[Item], src: &str, line_starts: &LineStarts) -> HashMap<usize, usize> {
+    let mut runs = HashMap::new();
+    let mut run = 0;
+    let mut prev_end = None;
+    for item in items {
+        let range = item_range(item, src, line_starts);
+        if !matches!(item, Item::Use(_)) {
+            prev_end = None;
+            continue;
+        }
+        let continues = prev_end.is_some_and(|end| src[end..range.start].matches('\n').count() < 2);
+        if !continues && !runs.is_empty() {
+            run += 1;
+        }
+        runs.insert(range.start, run);
+        prev_end = Some(range.end);
+    }
+    runs
+}
+
+fn uses_crlf(src: &str) -> bool {
+    let crlf = src.matches("\r\n").count();
+    let lf = src.matches('\n').count();
+    crlf > lf - crlf
+}
+
+/// Returns `config` with the category order that a `#![reorder(...)]`
+/// attribute among the crate attributes `attrs` asks for, if there is one.
+fn with_file_order(attrs: &[Attribute], config: &Config) -> Result<Option<Config>> {
+    Ok(reorder_attribute(attrs)?.map(|(_, category_order)| Config {
+        category_order,
+        ..config.clone()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    thread_local! {
+        pub(super) static COLUMN_STEPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    #[test]
+    fn test_region_markers() {
+        let src = "// region A\n  //#region B\nfn a() {} // region C\n// regional\n// endregion\n";
+        assert_eq!(
+            region_markers(src, 0..src.len()),
+            vec![(0, 11, true), (14, 25, true), (60, 72, false)]
+        );
+    }
+
+    #[test]
+    fn test_item_range_spans_doc_attributes() {
+        let src = "fn z() {}\n// Note.\n#[doc = \"One.\"]\n#[inline]\n#[doc = \"Two\n lines.\"]\n\
+                   #[doc = r#\"Raw \"quoted\".\"#]\nfn a() {}\n";
+        let file = syn::parse_file(src).unwrap();
+        let line_starts = line_start_offsets(src);
+
+        let range = item_range(&file.items[1], src, &line_starts);
+        assert_eq!(
+            &src[range],
+            "// Note.\n#[doc = \"One.\"]\n#[inline]\n#[doc = \"Two\n lines.\"]\n\
+             #[doc = r#\"Raw \"quoted\".\"#]\nfn a() {}"
+        );
+    }
+
+    #[test]
+    fn test_item_range_spans_attributes_across_blank_lines() {
+        let src = "fn z() {}\n\n/// Doc.\n\n#[inline]\nfn a() {}\n";
+        let file = syn::parse_file(src).unwrap();
+        let line_starts = line_start_offsets(src);
+
+        let range = item_range(&file.items[1], src, &line_starts);
+        assert_eq!(&src[range], "/// Doc.\n\n#[inline]\nfn a() {}");
+    }
+
+    #[test]
+    fn test_line_start_offsets() {
+        let src = "line1\nline2\nline3";
+        let starts = line_start_offsets(src);
+        assert_eq!(*starts, [0, 6, 12, 17]);
+    }
+
+    #[test]
+    fn test_line_start_offsets_ascii_lengths() {
+        let starts = line_start_offsets("ab\n\tcé d\néé\nxyz");
+        assert_eq!(*starts, [0, 3, 10, 15, 18]);
+        assert_eq!(starts.ascii_lengths, [2, 2, 0, 3, 0]);
+    }
+
+    #[test]
+    fn test_line_start_offsets_empty() {
+        let src = "";
+        let starts = line_start_offsets(src);
+        assert_eq!(*starts, [0]);
+    }
+
+    #[test]
+    fn test_line_start_offsets_single_line() {
+        let src = "hello";
+        let starts = line_start_offsets(src);
+        assert_eq!(*starts, [0, 5]);
+    }
+
+    #[test]
+    fn test_blank_lines_within() {
+        assert_eq!(Category::PubUse.blank_lines_within(), 0);
+        assert_eq!(Category::Use.blank_lines_within(), 0);
+        assert_eq!(Category::Macro.blank_lines_within(), 1);
+        assert_eq!(Category::Mod.blank_lines_within(), 0);
+        assert_eq!(Category::InlineMod.blank_lines_within(), 1);
+        assert_eq!(Category::ExternCrate.blank_lines_within(), 0);
+        assert_eq!(Category::Type.blank_lines_within(), 0);
+        assert_eq!(Category::Const.blank_lines_within(), 0);
+        assert_eq!(Category::Trait.blank_lines_within(), 1);
+        assert_eq!(Category::Typedef.blank_lines_within(), 1);
+        assert_eq!(Category::Impl.blank_lines_within(), 1);
+        assert_eq!(Category::Fn.blank_lines_within(), 1);
+        assert_eq!(Category::Tests.blank_lines_within(), 1);
+    }
+
+    #[test]
+    fn test_find_references() {
+        let src = "impl Foo { fn f(_: Bar) {} }\nstruct Foo(Bar, Baz);\nstruct Bar;\n";
+        let names = ["Foo".to_string(), "Bar".to_string(), "Baz".to_string()];
+        let ranges = HashMap::from([("Foo".to_string(), (29, 50)), ("Bar".to_string(), (51, 62))]);
+        let refs = find_references(&names, &ranges, src);
+        assert_eq!(refs["Foo"], ["Bar", "Baz"]);
+        assert!(refs["Bar"].is_empty());
+        assert!(refs["Baz"].is_empty());
+    }
+
+    #[test]
+    fn test_category_orders_sections() {
+        let file = syn::parse_file(
+            "pub use a::A;\npub(crate) use b::B;\nuse std::fs;\nconst A: u8 = 1;\nfn f() {}\n",
+        )
+        .unwrap();
+        let cats: Vec<Category> = file.items.iter().map(category).collect();
+        assert_eq!(
+            cats,
+            [
+                Category::PubUse,
+                Category::Use,
+                Category::Use,
+                Category::Const,
+                Category::Fn
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment_bytes() {
+        assert_eq!(comment_bytes("fn a() {} // note\n"), 7);
+        assert_eq!(comment_bytes("/* outer /* inner */ */ fn a() {}"), 23);
+        assert_eq!(comment_bytes("/// docs\nfn a() {}\n"), 8);
+        assert_eq!(comment_bytes("const A: &str = \"// not a comment\";"), 0);
+        assert_eq!(comment_bytes("const A: &str = r#\"/* \"no\" */\"#;"), 0);
+        assert_eq!(comment_bytes("const A: &[u8] = br\"//\"; // yes"), 6);
+        assert_eq!(comment_bytes("const Q: char = '\"'; // yes"), 6);
+        assert_eq!(comment_bytes("const Q: char = '\\''; // yes"), 6);
+        assert_eq!(comment_bytes("fn a<'a>(x: &'a str) {} // yes"), 6);
+    }
+
+    #[test]
+    fn test_header_to_string() {
+        let src = "//! Docs.\n//! More.\n\n\n// Why.\n#![deny(missing_docs)] // strict\n#![allow(dead_code)]\n\nfn a() {}\n";
+        let file = syn::parse_file(src).unwrap();
+        assert_eq!(
+            header_to_string(&file.attrs, src, &line_start_offsets(src), ""),
+            "//! Docs.\n//! More.\n\n// Why.\n#![deny(missing_docs)] // strict\n#![allow(dead_code)]"
+        );
+
+        let src =
+            "#![a]\n// Detached.\n\n#![b] /* inline */ #![c]\n/* Block. */\n#![d]\n\nfn a() {}\n";
+        let file = syn::parse_file(src).unwrap();
+        assert_eq!(
+            header_to_string(&file.attrs, src, &line_start_offsets(src), ""),
+            "#![a]\n// Detached.\n\n#![b] /* inline */\n#![c]\n/* Block. */\n#![d]"
+        );
+    }
+
+    #[test]
+    fn test_is_generated() {
+        let config = Config::default();
+        assert!(is_generated("// @generated by prost\nfn a() {}\n", &config));
+        assert!(is_generated("/*\n * @generated\n */\n", &config));
+        assert!(!is_generated(
+            "const MARKER: &str = \"@generated\";\n",
+            &config
+        ));
+        assert!(!is_generated("\n\n\n\n\n// @generated\n", &config));
+
+        let config = Config {
+            generated_marker: String::new(),
+            ..Config::default()
+        };
+        assert!(!is_generated("// @generated\n", &config));
+    }
+
+    #[test]
+    fn test_category_test_functions() {
+        let file = syn::parse_file(
+            "#[test]\nfn a() {}\n#[tokio::test]\nasync fn b() {}\n#[inline]\nfn c() {}\n",
+        )
+        .unwrap();
+        let cats: Vec<Category> = file.items.iter().map(category).collect();
+        assert_eq!(cats, [Category::Tests, Category::Tests, Category::Fn]);
+    }
+
+    #[test]
+    fn test_category_cfg_test_items() {
+        let file = syn::parse_file(
+            "#[cfg(test)]\nconst FIXTURE: &str = \"\";\n#[cfg(test)]\nfn helper() {}\n#[cfg(not(unix))]\nfn other() {}\n",
+        )
+        .unwrap();
+        let cats: Vec<Category> = file.items.iter().map(category).collect();
+        assert_eq!(cats, [Category::Tests, Category::Tests, Category::Fn]);
+    }
+
+    #[test]
+    fn test_category_cfg_attr_test_items() {
+        let file = syn::parse_file(
+            "\
+#[cfg_attr(feature = \"x\", cfg(test))]
+mod gated {}
+#[cfg_attr(unix, cfg_attr(feature = \"x\", cfg(all(test, feature = \"y\"))))]
+fn nested() {}
+#[cfg(all(test, feature = \"y\"))]
+mod feature_tests {}
+#[cfg_attr(test, ignore)]
+mod always_built {}
+#[cfg_attr(test, derive(Debug))]
+struct Thing;
+",
+        )
+        .unwrap();
+        let cats: Vec<Category> = file.items.iter().map(category).collect();
+        assert_eq!(
+            cats,
+            [
+                Category::Tests,
+                Category::Tests,
+                Category::Tests,
+                Category::InlineMod,
+                Category::Typedef
+            ]
+        );
+    }
+
+    #[test]
+    fn test_item_moves() {
+        let src = "#![allow(dead_code)]\n\nfn run() {}\n\n/// Docs.\nstruct Foo;\n\nuse std::fs;\n";
+        let moves = item_moves(src, &Config::default()).unwrap();
+        let positions: Vec<_> = moves
+            .iter()
+            .map(|m| (m.category, m.name.as_deref(), m.original_line, m.new_line))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                (Category::Use, None, 8, 3),
+                (Category::Typedef, Some("Foo"), 5, 5),
+                (Category::Fn, Some("run"), 3, 8),
+            ]
+        );
+
+        assert!(
+            item_moves("// reorder:skip\nfn b() {}\n", &Config::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_leading_comment_start() {
+        let src = "fn a() {}\n\n// note\n//// banner\nfn b() {}\n";
+        assert_eq!(leading_comment_start(src, 31), 11);
+
+        let src = "fn a() {}\n//! inner\nfn b() {}\n";
+        assert_eq!(leading_comment_start(src, 20), 20);
+    }
+
+    #[test]
+    fn test_use_path() {
+        let path = |src: &str| {
+            let item: syn::ItemUse = syn::parse_str(src).unwrap();
+            use_path(&item.tree)
+        };
+        assert_eq!(path("use std::io::Read;"), ["std", "io", "Read"]);
+        assert_eq!(path("use std::{fs, io};"), ["std"]);
+        assert_eq!(path("pub use a::b as c;"), ["a", "b"]);
+        assert_eq!(path("use super::*;"), ["super", "*"]);
+    }
+
+    #[test]
+    fn test_uses_crlf() {
+        assert!(uses_crlf("a\r\nb\r\nc\n"));
+        assert!(!uses_crlf("a\nb\nc\r\n"));
+        assert!(!uses_crlf(""));
+    }
+
+    #[test]
+    fn test_trailing_comment_end() {
+        let src = "const A: u8 = 1; // limit\nconst B: u8 = 2;\n";
+        assert_eq!(trailing_comment_end(src, 16), 25);
+
+        let src = "const A: u8 = 1;\n// next\nconst B: u8 = 2;\n";
+        assert_eq!(trailing_comment_end(src, 16), 16);
+
+        let src = "const A: u8 = 1; /* a */ /* b */\nconst B: u8 = 2;\n";
+        assert_eq!(trailing_comment_end(src, 16), 32);
+
+        let src = "const A: u8 = 1; /** B. */ const B: u8 = 2;\n";
+        assert_eq!(trailing_comment_end(src, 16), 16);
+    }
+
+    #[test]
+    fn test_partition_public_first_keeps_unscoped_items_in_place() {
+        let entry = |text: &str, public: Option<bool>| Entry {
+            attached: Vec::new(),
+            category: Category::Fn,
+            group: 0,
+            line: 1,
+            members: Vec::new(),
+            name: None,
+            public,
+            self_type: None,
+            text: text.to_string(),
+            unmatched: false,
+            use_path: Vec::new(),
+        };
+        let mut bucket = vec![
+            entry("a", Some(false)),
+            entry("m", None),
+            entry("b", Some(true)),
+            entry("c", Some(false)),
+            entry("d", Some(true)),
+        ];
+
+        partition_public_first(&mut bucket);
+
+        let texts: Vec<&str> = bucket.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["b", "m", "d", "a", "c"]);
+    }
+
+    #[test]
+    fn test_reorder_source_bom() {
+        let src = "\u{feff}#!/usr/bin/env run\nfn run() {}\n\nuse std::fs;\n";
+        assert_eq!(
+            reorder_source(src).unwrap(),
+            "\u{feff}#!/usr/bin/env run\n\nuse std::fs;\n\nfn run() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_reorder_source_crlf() {
+        let src = "fn run() {}\r\n\r\nuse std::fs;\r\n";
+        assert_eq!(
+            reorder_source(src).unwrap(),
+            "use std::fs;\r\n\r\nfn run() {}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_reorder_source() {
+        let src = "fn run() {}\n\nuse std::fs;\n";
+        assert_eq!(
+            reorder_source(src).unwrap(),
+            "use std::fs;\n\nfn run() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_reorder_source_idempotent() {
+        let corpus = [
+            include_str!("lib.rs"),
+            include_str!("config.rs"),
+            include_str!("diff.rs"),
+            include_str!("ignore.rs"),
+            include_str!("main.rs"),
+            "impl Display for Wrapper {}\n\nstruct Wrapper(Inner);\n\nstruct Inner;\n",
+            "struct A;\nimpl A {}\n// note\n\n\nfn f() {}\nuse b::B;\n\n\n\nuse std::fs;\n",
+            "#[rustfmt::skip]\nfn pinned() {}\nmod m {\n    fn b() {}\n    fn a() {}\n}\nconst C: u8 = 1;\n",
+            "#![allow(dead_code)]\n//! Docs.\n\n\n#[cfg(test)]\nmod tests {}\n#[test]\nfn t() {}\n",
+        ];
+        let configs = [
+            Config::default(),
+            Config {
+                blank_lines_between: 2,
+                blank_lines_within: Category::DEFAULT_ORDER.map(|c| (c, 0)).into(),
+                collapse_blank_lines: true,
+                impls_with_types: true,
+                preserve_cfg_groups: true,
+                preserve_regions: true,
+                preserve_use_groups: true,
+                pub_first: true,
+                sort_fields: true,
+                sort_impl_items: true,
+                sort_imports: true,
+                sort_within: true,
+                spacing_only: true,
+                trim_blank_lines: true,
+                types_with_consts: true,
+                ..Config::default()
+            },
+        ];
+
+        for config in &configs {
+            for src in corpus {
+                let (once, _) = reorder_pass(src, config).unwrap();
+                let (twice, _) = reorder_pass(&once, config).unwrap();
+                assert_eq!(once, twice, "not idempotent for:\n{src}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_impl_items_snippet() {
+        let snippet = |src: &str| {
+            let file = syn::parse_file(src).unwrap();
+            impl_items_snippet(&file.items[0], src, &line_start_offsets(src))
+        };
+
+        assert_eq!(
+            snippet(
+                "impl Foo {\n    fn b(&self) {}\n    pub fn a(&self) {}\n    \
+                 /// Makes one.\n    pub fn new() -> Self { Foo }\n    const N: u8 = 1;\n}\n"
+            )
+            .as_deref(),
+            Some(
+                "impl Foo {\n    const N: u8 = 1;\n    /// Makes one.\n    \
+                 pub fn new() -> Self { Foo }\n    pub fn a(&self) {}\n    fn b(&self) {}\n}"
+            )
+        );
+        assert_eq!(
+            snippet("impl Foo {\n    fn a() {}\n    fn build() -> Option<Foo> {}\n}\n").as_deref(),
+            Some("impl Foo {\n    fn build() -> Option<Foo> {}\n    fn a() {}\n}"),
+        );
+        assert_eq!(
+            snippet("impl Foo {\n    fn b() {}\n    m!();\n    fn a() {}\n}\n"),
+            None
+        );
+        assert_eq!(
+            snippet("impl Foo {\n    fn a() {}\n    fn b() {}\n}\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_section_splits_statics_when_listed() {
+        let file = syn::parse_file(
+            "const A: u8 = 1;\nstatic B: u8 = 2;\nstatic mut C: u8 = 3;\ntype D = u8;\n",
+        )
+        .unwrap();
+        let sections = |config: &Config| -> Vec<Category> {
+            file.items
+                .iter()
+                .map(|item| section(item, config))
+                .collect()
+        };
+        let ordered = |names: &str| Config {
+            category_order: parse_category_list(names).unwrap(),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            sections(&Config::default()),
+            [
+                Category::Const,
+                Category::Const,
+                Category::Const,
+                Category::Type
+            ]
+        );
+        assert_eq!(
+            sections(&ordered("static")),
+            [
+                Category::Const,
+                Category::Static,
+                Category::Static,
+                Category::Type
+            ]
+        );
+        assert_eq!(
+            sections(&ordered("static_mut,static")),
+            [
+                Category::Const,
+                Category::Static,
+                Category::StaticMut,
+                Category::Type
+            ]
+        );
+        assert_eq!(
+            sections(&Config {
+                types_with_consts: true,
+                ..ordered("static_mut")
+            }),
+            [
+                Category::Const,
+                Category::Const,
+                Category::StaticMut,
+                Category::Const
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_splits_trait_aliases_when_listed() {
+        let file = syn::parse_file("trait A {}\ntrait B = A + Send;\n").unwrap();
+        let sections = |config: &Config| -> Vec<Category> {
+            file.items
+                .iter()
+                .map(|item| section(item, config))
+                .collect()
+        };
+
+        assert_eq!(
+            sections(&Config::default()),
+            [Category::Trait, Category::Trait]
+        );
+        assert_eq!(
+            sections(&Config {
+                category_order: vec![Category::TraitAlias],
+                ..Config::default()
+            }),
+            [Category::Trait, Category::TraitAlias]
+        );
+    }
+
+    #[test]
+    fn test_section_splits_async_fns_when_listed() {
+        let file = syn::parse_file(
+            "async fn a() {}\nfn b() {}\n#[test]\nasync fn c() {}\nimpl X { async fn d() {} }\n",
+        )
+        .unwrap();
+        let sections = |config: &Config| -> Vec<Category> {
+            file.items
+                .iter()
+                .map(|item| section(item, config))
+                .collect()
+        };
+
+        assert_eq!(
+            sections(&Config::default()),
+            [Category::Fn, Category::Fn, Category::Tests, Category::Impl]
+        );
+        assert_eq!(
+            sections(&Config {
+                category_order: vec![Category::AsyncFn],
+                ..Config::default()
+            }),
+            [
+                Category::AsyncFn,
+                Category::Fn,
+                Category::Tests,
+                Category::Impl
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trim_blank_lines() {
+        assert_eq!(trim_blank_lines("\n  \n//! Docs.\n\n\n"), "//! Docs.\n");
+        assert_eq!(trim_blank_lines("    fn a() {}\n \n"), "    fn a() {}\n");
+        assert_eq!(trim_blank_lines("fn a() {}"), "fn a() {}");
+        assert_eq!(trim_blank_lines("fn a() {}  "), "fn a() {}  ");
+        assert_eq!(trim_blank_lines("\n\n"), "");
+    }
+
+    #[test]
+    fn test_category_proc_macros() {
+        let file = syn::parse_file(
+            "#[proc_macro]\npub fn a(input: TokenStream) -> TokenStream { input }\n\
+             #[proc_macro_derive(Named, attributes(name))]\npub fn b(input: TokenStream) -> TokenStream { input }\n\
+             #[proc_macro_attribute]\npub fn c(_: TokenStream, item: TokenStream) -> TokenStream { item }\n\
+             #[inline]\npub fn d() {}\n",
+        )
+        .unwrap();
+        let categories: Vec<Category> = file.items.iter().map(category).collect();
+
+        assert_eq!(
+            categories,
+            [
+                Category::ProcMacro,
+                Category::ProcMacro,
+                Category::ProcMacro,
+                Category::Fn
+            ]
+        );
+    }
+
+    #[test]
+    fn test_has_order_dependent_layout() {
+        let file = syn::parse_file(
+            "#[repr(C)] struct A;\n#[repr(packed(2))] struct B;\n#[repr(u8)] enum C {}\n\
+             #[derive(Debug, std::cmp::PartialOrd)] struct D;\n#[derive(Debug)] struct E;\n",
+        )
+        .unwrap();
+        let layouts: Vec<bool> = file
+            .items
+            .iter()
+            .map(|item| has_order_dependent_layout(item_attributes(item)))
+            .collect();
+
+        assert_eq!(layouts, [true, true, false, true, false]);
+    }
+
+    #[test]
+    fn test_reorder_partial() {
+        let config = Config {
+            force_partial: true,
+            ..Config::default()
+        };
+        let src = "fn b() {}\nfn a() {\n}\n\n\nstruct S {\n    field: u8\n";
+
+        assert_eq!(
+            reorder_source_with(src, &config).unwrap(),
+            "fn a() {\n}\n\nfn b() {}\n\n\nstruct S {\n    field: u8\n"
+        );
+        assert!(reorder_source_with("struct S {\n", &config).is_err());
+        assert!(reorder_source(src).is_err());
+    }
+
+    #[test]
+    fn test_reorder_source_parse_error() {
+        assert!(reorder_source("fn broken( {").is_err());
+    }
+
+    #[test]
+    fn test_category_macro_definition_vs_invocation() {
+        let file = syn::parse_file(
+            "macro_rules! square { ($x:expr) => { $x * $x }; }\nlazy_static! { static ref A: u8 = 1; }\n",
+        )
+        .unwrap();
+        let cats: Vec<Category> = file.items.iter().map(category).collect();
+        assert_eq!(cats, [Category::Macro, Category::Fn]);
+    }
+
+    #[test]
+    fn test_column_to_byte() {
+        let src = "ab\n// é漢\nx";
+        assert_eq!(column_to_byte(src, 0, 1), 1);
+        assert_eq!(column_to_byte(src, 3, 4), 8);
+        assert_eq!(column_to_byte(src, 3, 5), 11);
+        assert_eq!(column_to_byte(src, 12, 1), src.len());
+    }
+
+    #[test]
+    fn test_span_range_tabs_and_non_ascii() {
+        let src = "mod m {\n\t\tfn é() {}\t// ✓\n\tconst Ω: u8 = 1;\n}\n";
+        let line_starts = line_start_offsets(src);
+        let file = syn::parse_file(src).unwrap();
+        let Item::Mod(item_mod) = &file.items[0] else {
+            unreachable!()
+        };
+        let (_, items) = item_mod.content.as_ref().unwrap();
+
+        let snippets: Vec<String> = items
+            .iter()
+            .map(|item| item_snippet(item, src, &line_starts))
+            .collect();
+        assert_eq!(snippets, ["fn é() {}\t// ✓", "const Ω: u8 = 1;"]);
+    }
+
+    #[test]
+    fn test_span_range_on_one_long_line() {
+        // Columns before the first non-ASCII character map straight to byte
+        // offsets, and those after it are counted on from there.
+        let src = "fn a() {} const Ω: u8 = 1; fn é() {} struct S;";
+        let line_starts = line_start_offsets(src);
+        let file = syn::parse_file(src).unwrap();
+
+        let snippets: Vec<_> = file
+            .items
+            .iter()
+            .map(|item| &src[span_range(item.span(), src, &line_starts)])
+            .collect();
+        assert_eq!(
+            snippets,
+            ["fn a() {}", "const Ω: u8 = 1;", "fn é() {}", "struct S;"]
+        );
+    }
+
+    #[test]
+    fn test_column_lookups_scale_linearly() {
+        // A non-ASCII character at the start of the line leaves every column
+        // after it to be walked from a checkpoint.
+        let source = |count: usize| {
+            let items: Vec<_> = (0..count)
+                .rev()
+                .map(|i| format!("fn f{i}() {{}}"))
+                .collect();
+            format!("/* é */ {}", items.join(" "))
+        };
+        let steps = |src: &str| {
+            COLUMN_STEPS.with(|steps| steps.set(0));
+            reorder_source(src).unwrap();
+            COLUMN_STEPS.with(std::cell::Cell::get)
+        };
+
+        let small = steps(&source(500));
+        let large = steps(&source(2000));
+        assert!(small > 0);
+        // Four times the items take about four times the steps, where walking
+        // each column from the start of the line would take sixteen.
+        assert!(
+            large <= small * 5,
+            "{small} steps for 500 items, {large} for 2000"
+        );
+    }
+}
@@ -1,57 +1,376 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use anyhow::{Context, Result, anyhow, bail};
+use cargo_refmt::{
+    Category, Config, Edition, ItemMove, comment_bytes, explain_items, is_generated, item_moves,
+    reorder_range, reorder_source_with,
+};
+use clap::{ArgAction, Parser, ValueEnum};
+use ignore::Gitignore;
+
+mod diff;
+mod editorconfig;
+mod ignore;
+mod modules;
+
+/// Exit status for files that could not be read, parsed or written.
+const EXIT_ERROR: u8 = 2;
+/// Exit status for a command line or configuration that cannot be acted on.
+const EXIT_USAGE: u8 = 3;
+
+struct Options {
+    backup: bool,
+    color: bool,
+    continue_on_error: bool,
+    deny_comment_loss: bool,
+    editorconfig: bool,
+    fail_on_parse_error: bool,
+    force: bool,
+    mode: Mode,
+    /// Show how many files have been processed on an interactive stderr.
+    progress: bool,
+    report: bool,
+    /// Directory that files are written to, mirroring their relative paths,
+    /// instead of being rewritten in place.
+    target_dir: Option<PathBuf>,
+    verbosity: Verbosity,
+    verify: bool,
+}
 
-use anyhow::{Context, Result, bail};
-use clap::Parser;
-use syn::spanned::Spanned;
-use syn::{Attribute, File, Item};
-
-type Cat = usize;
+/// The outcome for one file, with the text to print on stdout: the diff in
+/// [`Mode::Diff`], the reordered source in [`Mode::Stdout`] and nothing
+/// otherwise. `moves` is only filled in for `--report-json`, `--diff-stat` and
+/// `-vv`, and for `-v` with `--impls-with-types`.
+struct Report {
+    moves: Vec<ItemMove>,
+    output: String,
+    status: FileStatus,
+    /// Warnings for the file, printed by the main thread so that those of
+    /// parallel workers do not interleave with each other or the progress.
+    warnings: Vec<String>,
+}
 
 #[derive(Parser)]
 #[command(name = "refmt")]
 #[command(bin_name = "cargo refmt")]
 #[command(version, about = "Sort items consistently in Rust source files")]
 struct Args {
+    /// Copy each file to `<file>.bak` before rewriting it
+    #[arg(long, conflicts_with_all = ["check", "diff", "diff_stat", "list_changed", "stdout"])]
+    backup: bool,
+    /// Report files that would be reordered without rewriting them
+    #[arg(long)]
+    check: bool,
+    /// Collapse runs of blank lines in front of an item to a single one
+    #[arg(long)]
+    collapse_blank_lines: bool,
+    /// Color diff output: `auto` colors it only when stdout is a terminal
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = Color::Auto)]
+    color: Color,
+    /// Read category ordering from a `reorder.toml` file
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Use the `reorder.toml` nearest to each file, in its directory or an ancestor
+    #[arg(long, conflicts_with = "config")]
+    config_discover: bool,
+    /// Keep processing remaining files when one fails, then exit non-zero
+    #[arg(long)]
+    continue_on_error: bool,
+    /// Fail instead of reordering a file when that would drop comments
+    #[arg(long)]
+    deny_comment_loss: bool,
+    /// Only descend N levels of subdirectories below each directory argument
+    #[arg(long, value_name = "N")]
+    depth: Option<usize>,
+    /// Print a unified diff for every file that would be reordered without rewriting it
+    #[arg(long)]
+    diff: bool,
+    /// Print how many items would move in every file that would be reordered, without rewriting it
+    #[arg(long, conflicts_with_all = ["check", "diff", "list_changed", "stdout"])]
+    diff_stat: bool,
+    /// Rust edition of the sources: 2015, 2018, 2021 or 2024 [default: 2024]
+    #[arg(long, value_name = "EDITION")]
+    edition: Option<Edition>,
+    /// Skip files and directories matching a glob; may be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Dry run: print the section every item is sorted into and why, without rewriting anything
+    #[arg(long, conflicts_with_all = ["backup", "check", "diff", "diff_stat", "list_changed", "stdout"])]
+    explain: bool,
+    /// Fail on files that do not parse instead of skipping them with a warning
+    #[arg(long)]
+    fail_on_parse_error: bool,
+    /// Process the paths listed in FILE, one per line, or read from stdin with `-`
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["paths", "staged", "stdin"])]
+    files_from: Option<PathBuf>,
+    /// Emit impl blocks directly after the type they implement
+    #[arg(long)]
+    impls_with_types: bool,
+    /// Also scan directories whose name starts with `.`
+    #[arg(long)]
+    include_hidden: bool,
+    /// Also process the files of `mod foo;` declarations in every input file
+    #[arg(long)]
+    follow_mods: bool,
+    /// Overwrite existing `.bak` files when used with `--backup`
+    #[arg(long, requires = "backup")]
+    force: bool,
+    /// Experimental: reorder the items of an unparseable file up to the first broken one
+    #[arg(long)]
+    force_partial: bool,
+    /// Number of files to process in parallel [default: number of CPUs]
+    #[arg(long, short = 'j', value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Skip files larger than BYTES, such as huge generated sources
+    #[arg(long, value_name = "BYTES")]
+    max_file_size: Option<u64>,
+    /// Print the path of every file that would be reordered, without rewriting any
+    #[arg(long, conflicts_with_all = ["check", "diff"])]
+    list_changed: bool,
+    /// Ignore `.editorconfig` files instead of taking line endings and final newlines from them
+    #[arg(long)]
+    no_editorconfig: bool,
+    /// Also process files ignored by `.gitignore` when walking directories
+    #[arg(long)]
+    no_gitignore: bool,
+    /// Leave every item where it is and only normalize the blank lines between them
+    #[arg(long)]
+    no_reorder_within_group: bool,
+    /// Only reorder items of CATEGORY, leaving the others in place; can be repeated
+    #[arg(long, value_name = "CATEGORY")]
+    only: Vec<Category>,
+    /// Keep runs of adjacent items with the same `#[cfg(...)]` together in source order
+    #[arg(long)]
+    preserve_cfg_groups: bool,
+    /// Keep `// region` ... `// endregion` blocks together in source order
+    #[arg(long)]
+    preserve_regions: bool,
+    /// Keep blank-line separated runs of `use` declarations as separate groups
+    #[arg(long)]
+    preserve_use_groups: bool,
+    /// Place `pub` and `pub(crate)` items before private ones within each category
+    #[arg(long)]
+    pub_first: bool,
+    /// Print nothing but errors; the exit status still reports the outcome
+    #[arg(long, short = 'q', conflicts_with = "verbose")]
+    quiet: bool,
+    /// Sort struct fields and enum variants by name where their order is not significant
+    #[arg(long)]
+    sort_fields: bool,
+    /// Sort the items within each impl block by kind, visibility and name
+    #[arg(long)]
+    sort_impl_items: bool,
+    /// Sort `use` declarations by path within each import group
+    #[arg(long)]
+    sort_imports: bool,
+    /// Sort items within each category alphabetically by name
+    #[arg(long)]
+    sort_within: bool,
+    /// Remove `#![reorder(...)]` attributes from the files they reorder
+    #[arg(long)]
+    strip_reorder_attribute: bool,
+    /// Separate the paths of `--files-from` by NUL bytes instead of newlines
+    #[arg(long, requires = "files_from")]
+    null: bool,
+    /// Only reorder items entirely within lines START to END (inclusive) of stdin
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range)]
+    range: Option<RangeInclusive<usize>>,
+    /// Write a JSON report of where every item of every file was placed
+    #[arg(long, value_name = "FILE")]
+    report_json: Option<PathBuf>,
+    /// Process only the Rust files staged in git instead of any paths
+    #[arg(long, conflicts_with_all = ["paths", "stdin"])]
+    staged: bool,
+    /// Read source from stdin and write the reordered result to stdout
+    #[arg(long)]
+    stdin: bool,
+    /// Print the reordered source of each file to stdout instead of rewriting it
+    #[arg(long, conflicts_with_all = ["check", "diff", "list_changed"])]
+    stdout: bool,
+    /// Write every file under DIR at its path relative to the current directory instead of in place
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["backup", "check", "diff", "diff_stat", "explain", "list_changed", "stdout"]
+    )]
+    target_dir: Option<PathBuf>,
+    /// Remove blank lines at the start of each file and extra ones at its end
+    #[arg(long)]
+    trim_blank_lines: bool,
+    /// Sort type aliases by name together with constants and statics
+    #[arg(long)]
+    types_with_consts: bool,
+    /// Reorder each reordered file again and fail if that changes it further
+    #[arg(long, conflicts_with = "strip_reorder_attribute")]
+    verify: bool,
+    /// Also list unchanged and generated files; repeat (`-vv`) to trace every item
+    #[arg(long, short = 'v', action = ArgAction::Count)]
+    verbose: u8,
     #[arg(value_name = "PATH")]
     paths: Vec<PathBuf>,
 }
 
-fn blank_lines_after(category: usize) -> usize {
-    match category {
-        0..=6 => 0,
-        _ => 1,
+struct Walk {
+    depth: Option<usize>,
+    exclude: Vec<String>,
+    follow_mods: bool,
+    include_hidden: bool,
+    max_file_size: Option<u64>,
+    respect_gitignore: bool,
+    /// Note the files skipped for their size on stderr.
+    verbose: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Color {
+    Always,
+    Auto,
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileStatus {
+    Changed,
+    Generated,
+    Unchanged,
+    /// Skipped because the file does not parse.
+    Unparseable,
+}
+
+/// An error in how the tool was invoked, exiting with [`EXIT_USAGE`].
+#[derive(Debug)]
+struct UsageError(String);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Check,
+    Diff,
+    DiffStat,
+    Explain,
+    List,
+    Stdout,
+    Write,
+}
+
+/// How much is printed to stderr besides errors, from `-q` up to `-vv`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+    Trace,
+}
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
-fn category(item: &Item) -> Cat {
-    if is_test_module(item) {
-        return 11;
+impl std::error::Error for UsageError {}
+
+fn ancestor_gitignores(dir: &Path) -> Result<Vec<Rc<Gitignore>>> {
+    let canonical = fs::canonicalize(dir)
+        .with_context(|| format!("canonicalize directory {}", dir.display()))?;
+    if canonical.join(".git").exists() {
+        return Ok(Vec::new());
     }
 
-    match item {
-        Item::Use(use_item) => use_category(use_item),
-        Item::Mod(_) => 3,
-        Item::ExternCrate(_) => 4,
-        Item::Type(_) => 5,
-        Item::Const(_) | Item::Static(_) => 6,
-        Item::Trait(_) | Item::TraitAlias(_) => 7,
-        Item::Struct(_) | Item::Enum(_) | Item::Union(_) => 8,
-        Item::Impl(_) => 9,
-        Item::Fn(_) | Item::ForeignMod(_) | Item::Macro(_) | Item::Verbatim(_) => 10,
-        _ => 10,
+    let mut gitignores = Vec::new();
+    for ancestor in canonical.ancestors().skip(1) {
+        let relative = canonical
+            .strip_prefix(ancestor)
+            .expect("ancestor is a prefix");
+        let mut prefix = String::new();
+        for component in relative.components() {
+            prefix.push_str(&component.as_os_str().to_string_lossy());
+            prefix.push('/');
+        }
+        if let Some(gitignore) = Gitignore::load(ancestor)? {
+            gitignores.push(Rc::new(gitignore.with_base(dir, prefix)));
+        }
+        if ancestor.join(".git").exists() {
+            gitignores.reverse();
+            return Ok(gitignores);
+        }
     }
+
+    // Ignore rules only apply inside the repository that contains them.
+    Ok(Vec::new())
+}
+
+/// Applies the options given on the command line on top of `config`.
+fn apply_args(config: &mut Config, args: &Args) {
+    if let Some(edition) = args.edition {
+        config.edition = edition;
+    }
+    config.collapse_blank_lines |= args.collapse_blank_lines;
+    config.force_partial |= args.force_partial;
+    config.impls_with_types |= args.impls_with_types;
+    if !args.only.is_empty() {
+        config.only = args.only.clone();
+    }
+    config.preserve_cfg_groups |= args.preserve_cfg_groups;
+    config.preserve_regions |= args.preserve_regions;
+    config.preserve_use_groups |= args.preserve_use_groups;
+    config.pub_first |= args.pub_first;
+    config.sort_fields |= args.sort_fields;
+    config.sort_impl_items |= args.sort_impl_items;
+    config.sort_imports |= args.sort_imports;
+    config.sort_within |= args.sort_within;
+    config.spacing_only |= args.no_reorder_within_group;
+    config.strip_reorder_attribute |= args.strip_reorder_attribute;
+    config.trim_blank_lines |= args.trim_blank_lines;
+    config.types_with_consts |= args.types_with_consts;
+}
+
+/// Returns a warning when reordering `src` into `out` drops comments, or
+/// fails if `deny` is set. The warning is left out under `--quiet`.
+fn check_comments(
+    name: &str,
+    src: &str,
+    out: &str,
+    deny: bool,
+    verbosity: Verbosity,
+) -> Result<Option<String>> {
+    if comment_bytes(out) >= comment_bytes(src) {
+        return Ok(None);
+    }
+    if deny {
+        bail!("reordering {name} would drop comments; leaving it unchanged");
+    }
+    Ok((verbosity > Verbosity::Quiet).then(|| format!("reordering {name} drops comments")))
 }
 
 fn collect_directory(
     dir: &Path,
     files: &mut Vec<PathBuf>,
     seen: &mut HashSet<PathBuf>,
+    walk: &Walk,
 ) -> Result<()> {
-    let mut queue = std::collections::VecDeque::from([dir.to_path_buf()]);
+    let gitignores = if walk.respect_gitignore {
+        ancestor_gitignores(dir)?
+    } else {
+        Vec::new()
+    };
+    let mut queue = std::collections::VecDeque::from([(dir.to_path_buf(), gitignores, 0)]);
+
+    while let Some((current, mut gitignores, depth)) = queue.pop_front() {
+        if walk.respect_gitignore
+            && let Some(gitignore) = Gitignore::load(&current)?
+        {
+            gitignores.push(Rc::new(gitignore));
+        }
 
-    while let Some(current) = queue.pop_front() {
         let mut entries = Vec::new();
         let read_dir = fs::read_dir(&current)
             .with_context(|| format!("read directory {}", current.display()))?;
@@ -69,11 +388,26 @@ fn collect_directory(
                 .file_type()
                 .with_context(|| format!("determine type for {}", path.display()))?;
 
+            if walk.respect_gitignore && ignore::is_ignored(&gitignores, &path, file_type.is_dir())
+            {
+                continue;
+            }
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            if is_excluded(&walk.exclude, &path, relative) {
+                continue;
+            }
+
             if file_type.is_dir() {
-                queue.push_back(path);
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if (walk.include_hidden || !hidden) && walk.depth.is_none_or(|max| depth < max) {
+                    queue.push_back((path, gitignores.clone(), depth + 1));
+                }
             } else if file_type.is_file() {
                 if is_rust_file(&path) {
-                    push_file(path, files, seen);
+                    let metadata = entry
+                        .metadata()
+                        .with_context(|| format!("inspect metadata for {}", path.display()))?;
+                    push_file(path, metadata.len(), files, seen, walk);
                 }
             } else if file_type.is_symlink() {
                 let metadata = fs::metadata(&path)
@@ -81,7 +415,7 @@ fn collect_directory(
                 if metadata.is_dir() {
                     continue;
                 } else if metadata.is_file() && is_rust_file(&path) {
-                    push_file(path, files, seen);
+                    push_file(path, metadata.len(), files, seen, walk);
                 }
             }
         }
@@ -90,200 +424,270 @@ fn collect_directory(
     Ok(())
 }
 
-fn collect_input_files(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+fn collect_input_files(paths: Vec<PathBuf>, walk: &Walk) -> Result<Vec<PathBuf>> {
     let mut files = Vec::new();
     let mut seen = HashSet::new();
 
     for path in paths {
-        collect_path(&path, &mut files, &mut seen)?;
+        if path.exists() || !path.to_string_lossy().contains(['*', '?', '[']) {
+            collect_path(&path, &mut files, &mut seen, walk)?;
+            continue;
+        }
+        let matches = expand_glob(&path);
+        if matches.is_empty() {
+            return Err(UsageError(format!("no files match {}", path.display())).into());
+        }
+        // Unlike files named explicitly, matched files must be Rust files.
+        for path in matches {
+            if path.is_dir() || is_rust_file(&path) {
+                collect_path(&path, &mut files, &mut seen, walk)?;
+            }
+        }
+    }
+
+    if walk.follow_mods {
+        // Declared modules are appended as they are found, so the files they
+        // declare in turn are visited too.
+        let mut index = 0;
+        let mut via_path = HashSet::new();
+        while let Some(path) = files.get(index) {
+            let src = fs::read_to_string(path)
+                .with_context(|| format!("read file {}", path.display()))?;
+            let declared = modules::declared_files(path, &src, via_path.contains(path));
+            for (module, through_attr) in declared {
+                if !is_excluded(&walk.exclude, &module, &module) {
+                    if through_attr {
+                        via_path.insert(module.clone());
+                    }
+                    let size = fs::metadata(&module).map_or(0, |metadata| metadata.len());
+                    push_file(module, size, &mut files, &mut seen, walk);
+                }
+            }
+            index += 1;
+        }
     }
 
-    if files.is_empty() {
-        bail!("no Rust files found");
+    if seen.is_empty() {
+        return Err(UsageError("no Rust files found".to_string()).into());
     }
 
     Ok(files)
 }
 
-fn collect_path(path: &Path, files: &mut Vec<PathBuf>, seen: &mut HashSet<PathBuf>) -> Result<()> {
+fn collect_path(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    seen: &mut HashSet<PathBuf>,
+    walk: &Walk,
+) -> Result<()> {
+    if is_excluded(&walk.exclude, path, path) {
+        return Ok(());
+    }
+
     let metadata =
         fs::metadata(path).with_context(|| format!("inspect metadata for {}", path.display()))?;
 
     if metadata.is_dir() {
-        collect_directory(path, files, seen)?;
+        collect_directory(path, files, seen, walk)?;
     } else if metadata.is_file() {
-        push_file(path.to_path_buf(), files, seen);
+        push_file(path.to_path_buf(), metadata.len(), files, seen, walk);
     }
 
     Ok(())
 }
 
-fn contains_test(expr: &syn::Expr) -> bool {
-    match expr {
-        syn::Expr::Path(path) => path.path.is_ident("test"),
-        syn::Expr::Tuple(tuple) => tuple.elems.iter().any(contains_test),
-        syn::Expr::Binary(bin) => contains_test(&bin.left) || contains_test(&bin.right),
-        syn::Expr::Group(group) => contains_test(&group.expr),
-        syn::Expr::Call(call) => {
-            if let syn::Expr::Path(path) = &*call.func
-                && (path.path.is_ident("any") || path.path.is_ident("all"))
-            {
-                return call.args.iter().any(contains_test);
-            }
-            false
-        }
-        _ => false,
-    }
-}
+/// Returns the `src` directory of the package containing the working
+/// directory, or the working directory itself outside of a package.
+fn default_path() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("determine current directory")?;
+    let Some(package) = cwd.ancestors().find(|dir| dir.join("Cargo.toml").is_file()) else {
+        return Ok(PathBuf::from("."));
+    };
 
-fn find_item_range(name: &str, src: &str) -> Option<(usize, usize)> {
-    let pattern = format!("{} {{", name);
-    if let Some(start) = src.find(&pattern) {
-        let mut brace_count = 0;
-        let mut in_body = false;
-        for (i, c) in src[start..].char_indices() {
-            if c == '{' {
-                brace_count += 1;
-                in_body = true;
-            } else if c == '}' {
-                brace_count -= 1;
-                if in_body && brace_count == 0 {
-                    return Some((start, start + i + 1));
-                }
-            }
-        }
+    let src = package.join("src");
+    if src.is_dir() {
+        Ok(src)
+    } else {
+        Ok(package.to_path_buf())
     }
+}
 
-    if let Some(start) = src.find(&format!("{};", name)) {
-        return Some((start, start + name.len() + 1));
+/// Renders `--diff-stat` output: a `path | N moved` line for each file, with
+/// the paths padded to the same width, and a total.
+fn diff_stat(stats: &[(String, usize)]) -> String {
+    let width = stats.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (path, moved) in stats {
+        out.push_str(&format!(" {path:width$} | {moved} moved\n"));
     }
-
-    None
+    let total: usize = stats.iter().map(|(_, moved)| moved).sum();
+    out.push_str(&format!(
+        " {} file(s) changed, {total} item(s) moved\n",
+        stats.len()
+    ));
+    out
 }
 
-fn find_references(names: &[String], src: &str) -> HashMap<String, Vec<String>> {
-    let mut refs: HashMap<String, Vec<String>> = HashMap::new();
-
-    for name in names {
-        refs.insert(name.clone(), Vec::new());
+/// Returns the index in `configs` of the configuration for files in `dir`:
+/// the first `reorder.toml` found in `dir` or its ancestors, or `configs[0]`
+/// when there is none. Results are cached per directory in `config_dirs`.
+fn discover_config(
+    dir: &Path,
+    config_dirs: &mut HashMap<PathBuf, usize>,
+    configs: &mut Vec<Config>,
+    args: &Args,
+) -> Result<usize> {
+    let mut visited = Vec::new();
+    let mut index = 0;
+    for ancestor in dir.ancestors() {
+        if let Some(&cached) = config_dirs.get(ancestor) {
+            index = cached;
+            break;
+        }
+        visited.push(ancestor.to_path_buf());
+        let path = ancestor.join("reorder.toml");
+        if path.is_file() {
+            let mut config = load_config(&path)?;
+            apply_args(&mut config, args);
+            configs.push(config);
+            index = configs.len() - 1;
+            break;
+        }
     }
+    for dir in visited {
+        config_dirs.insert(dir, index);
+    }
+    Ok(index)
+}
 
-    let name_to_range: HashMap<String, (usize, usize)> = names
-        .iter()
-        .filter_map(|n| {
-            let range = find_item_range(n, src)?;
-            Some((n.clone(), range))
-        })
-        .collect();
-
-    let mut i = 0;
-    let bytes = src.as_bytes();
-    while i < bytes.len() {
-        if bytes[i] == b'/' && i + 1 < bytes.len() {
-            if bytes[i + 1] == b'/' {
-                while i < bytes.len() && bytes[i] != b'\n' {
-                    i += 1;
-                }
-                continue;
-            } else if bytes[i + 1] == b'*' {
-                i += 2;
-                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
-                    i += 1;
-                }
-                i += 2;
-                continue;
-            }
-        }
+/// Expands a path containing glob patterns into the existing paths it
+/// matches, in sorted order.
+///
+/// Each component is matched against the entries of the directories matched
+/// so far, and a `**` component matches any number of directories. As in a
+/// shell, hidden entries only match a component that starts with `.`.
+fn expand_glob(pattern: &Path) -> Vec<PathBuf> {
+    let list = |dir: &Path| -> Vec<(PathBuf, String)> {
+        let read_dir = if dir.as_os_str().is_empty() {
+            fs::read_dir(".")
+        } else {
+            fs::read_dir(dir)
+        };
+        let Ok(read_dir) = read_dir else {
+            return Vec::new();
+        };
+        let mut entries: Vec<_> = read_dir
+            .flatten()
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                (dir.join(&name), name)
+            })
+            .collect();
+        entries.sort();
+        entries
+    };
 
-        if bytes[i].is_ascii_alphabetic() || bytes[i] == b'_' {
-            let start = i;
-            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
-                i += 1;
-            }
-            let word = &src[start..i];
-
-            if names.iter().any(|n| word == *n) {
-                for (name, range) in &name_to_range {
-                    if start >= range.0
-                        && start <= range.1
-                        && word != name
-                        && let Some(v) = refs.get_mut(name)
-                        && !v.contains(&word.to_string())
-                    {
-                        v.push(word.to_string());
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let text = component.as_os_str().to_string_lossy();
+        if text == "**" {
+            let mut dirs = Vec::new();
+            let mut queue: std::collections::VecDeque<_> = paths.into_iter().collect();
+            while let Some(dir) = queue.pop_front() {
+                for (path, name) in list(&dir) {
+                    let is_dir = fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir());
+                    if is_dir && !name.starts_with('.') {
+                        queue.push_back(path);
                     }
                 }
+                dirs.push(dir);
+            }
+            paths = dirs;
+        } else if text.contains(['*', '?', '[']) {
+            paths = paths
+                .iter()
+                .flat_map(|dir| list(dir))
+                .filter(|(_, name)| {
+                    (text.starts_with('.') || !name.starts_with('.'))
+                        && ignore::glob_match(&text, name)
+                })
+                .map(|(path, _)| path)
+                .collect();
+        } else {
+            for path in &mut paths {
+                path.push(component);
             }
-            continue;
         }
-        i += 1;
     }
 
-    refs
+    paths.retain(|path| !path.as_os_str().is_empty() && path.exists());
+    paths.sort();
+    paths.dedup();
+    paths
 }
 
-fn fn_item_name(item: &Item) -> String {
-    match item {
-        Item::Fn(fn_item) => fn_item.sig.ident.to_string(),
-        _ => String::new(),
+/// Renders `--explain` output for the file at `path`: a line for each item
+/// with its `syn::Item` variant, its name, the section it is sorted into and
+/// that section's position in the category order.
+fn explain(path: &Path, src: &str, config: &Config) -> Result<String> {
+    let items = explain_items(src, config).with_context(|| format!("parse {}", path.display()))?;
+    let mut out = String::new();
+    for item in items {
+        out.push_str(&format!(
+            "{}:{}: {} {} -> {} (#{})",
+            path.display(),
+            item.line,
+            item.kind,
+            item.name.as_deref().unwrap_or("(unnamed)"),
+            item.category.name(),
+            item.index
+        ));
+        if let Some(test) = item.test {
+            out.push_str(&format!(" [{test}]"));
+        }
+        out.push('\n');
     }
+    Ok(out)
 }
 
-fn fn_visibility_rank(item: &Item) -> u8 {
-    match item {
-        Item::Fn(fn_item) => match &fn_item.vis {
-            syn::Visibility::Public(_) => 0,
-            syn::Visibility::Restricted(_) => 1,
-            syn::Visibility::Inherited => 2,
-        },
-        _ => 0,
+/// Returns the key files are deduplicated by: the canonical form of `path`,
+/// or its absolute form if it cannot be resolved. On Windows, the `\\?\`
+/// prefix of canonical paths is removed so that both forms compare the same.
+fn file_key(path: &Path) -> PathBuf {
+    let key = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    if cfg!(windows) {
+        strip_verbatim_prefix(key)
+    } else {
+        key
     }
 }
 
-fn has_cfg_test(attrs: &[Attribute]) -> bool {
-    attrs.iter().any(|attr| {
-        if !attr.path().is_ident("cfg") {
-            return false;
-        }
-        match attr.parse_args::<syn::Expr>() {
-            Ok(expr) => contains_test(&expr),
-            Err(_) => false,
-        }
-    })
+/// Returns the [`item_moves`] of `src`, counting the calls in tests.
+fn file_moves(src: &str, config: &Config) -> Result<Vec<ItemMove>> {
+    #[cfg(test)]
+    tests::FILE_MOVES_CALLS.with(|calls| calls.set(calls.get() + 1));
+    item_moves(src, config)
 }
 
-fn header_to_string(attrs: &[Attribute], src: &str, line_starts: &[usize]) -> String {
-    if attrs.is_empty() {
-        return String::new();
-    }
-
-    let mut start = usize::MAX;
-    let mut end = 0usize;
-
-    for attr in attrs {
-        let range = span_range(attr.span(), line_starts, src.len());
-        start = start.min(range.start);
-        end = end.max(range.end);
-    }
-
-    src[start..end].to_string()
-}
-
-fn impl_type_name(impl_snippet: &str) -> String {
-    let impl_keyword = "impl";
-    if let Some(start) = impl_snippet.strip_prefix(impl_keyword) {
-        let trimmed = start.trim();
-        if let Some(pos) = trimmed.find('<') {
-            trimmed[..pos].trim().to_string()
-        } else if let Some(for_pos) = trimmed.find(" for ") {
-            let after_for = trimmed[for_pos + 5..].trim();
-            after_for.split_whitespace().next().unwrap_or(after_for).to_string()
+/// Returns whether an exclude pattern matches `path`, either as given or
+/// `relative` to the directory being walked. Patterns without a `/` match the
+/// file name alone.
+fn is_excluded(exclude: &[String], path: &Path, relative: &Path) -> bool {
+    let path = ignore::slash_path(path);
+    let relative = ignore::slash_path(relative);
+    let name = relative.rsplit('/').next().unwrap_or_default();
+
+    exclude.iter().any(|pattern| {
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.contains('/') {
+            let pattern = pattern.trim_start_matches("./");
+            ignore::glob_match(pattern, &relative) || ignore::glob_match(pattern, &path)
         } else {
-            trimmed.split_whitespace().next().unwrap_or(trimmed).to_string()
+            ignore::glob_match(pattern, name)
         }
-    } else {
-        String::new()
-    }
+    })
 }
 
 fn is_rust_file(path: &Path) -> bool {
@@ -293,401 +697,977 @@ fn is_rust_file(path: &Path) -> bool {
     }
 }
 
-fn is_std_crate(name: &str) -> bool {
-    name == "std"
-        || name == "core"
-        || name == "alloc"
-        || name.starts_with("std::")
-        || name.starts_with("core::")
-        || name.starts_with("alloc::")
-}
-
-fn is_test_module(item: &Item) -> bool {
-    match item {
-        Item::Mod(module) => has_cfg_test(&module.attrs),
-        _ => false,
+/// Renders `--report-json` output: a summary of how many files changed and,
+/// for each file, where its items were placed.
+fn json_report(files: &[(PathBuf, FileStatus, Vec<ItemMove>)]) -> String {
+    let changed = files
+        .iter()
+        .filter(|(_, status, _)| *status == FileStatus::Changed)
+        .count();
+    let mut out = format!(
+        "{{\n  \"summary\": {{ \"files\": {}, \"changed\": {changed} }},\n  \"files\": [",
+        files.len()
+    );
+
+    for (index, (path, status, moves)) in files.iter().enumerate() {
+        let status = match status {
+            FileStatus::Changed => "changed",
+            FileStatus::Generated => "generated",
+            FileStatus::Unchanged => "unchanged",
+            FileStatus::Unparseable => "unparseable",
+        };
+        out.push_str(if index == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!(
+            "    {{\n      \"path\": {},\n      \"status\": \"{status}\",\n      \"items\": [",
+            json_string(&path.display().to_string())
+        ));
+        for (index, item) in moves.iter().enumerate() {
+            let name = match &item.name {
+                Some(name) => json_string(name),
+                None => "null".to_string(),
+            };
+            out.push_str(if index == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!(
+                "        {{ \"category\": \"{}\", \"name\": {name}, \"original_line\": {}, \"new_line\": {} }}",
+                item.category.name(),
+                item.original_line,
+                item.new_line
+            ));
+        }
+        out.push_str(if moves.is_empty() {
+            "]\n    }"
+        } else {
+            "\n      ]\n    }"
+        });
     }
-}
 
-fn item_attributes(item: &Item) -> &[Attribute] {
-    match item {
-        Item::Const(item) => &item.attrs,
-        Item::Enum(item) => &item.attrs,
-        Item::ExternCrate(item) => &item.attrs,
-        Item::Fn(item) => &item.attrs,
-        Item::ForeignMod(item) => &item.attrs,
-        Item::Impl(item) => &item.attrs,
-        Item::Macro(item) => &item.attrs,
-        Item::Mod(item) => &item.attrs,
-        Item::Static(item) => &item.attrs,
-        Item::Struct(item) => &item.attrs,
-        Item::Trait(item) => &item.attrs,
-        Item::TraitAlias(item) => &item.attrs,
-        Item::Type(item) => &item.attrs,
-        Item::Union(item) => &item.attrs,
-        Item::Use(item) => &item.attrs,
-        Item::Verbatim(_) => &[],
-        _ => &[],
-    }
+    out.push_str(if files.is_empty() {
+        "]\n}\n"
+    } else {
+        "\n  ]\n}\n"
+    });
+    out
 }
 
-fn item_name(item: &Item) -> Option<String> {
-    match item {
-        Item::Struct(s) => Some(s.ident.to_string()),
-        Item::Enum(e) => Some(e.ident.to_string()),
-        Item::Union(u) => Some(u.ident.to_string()),
-        _ => None,
+fn json_string(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
     }
+    out.push('"');
+    out
 }
 
-fn item_snippet(item: &Item, src: &str, line_starts: &[usize]) -> String {
-    let mut range = span_range(item.span(), line_starts, src.len());
+/// Loads the configuration file at `path`, which is a usage error if invalid.
+fn load_config(path: &Path) -> Result<Config> {
+    Config::load(path).map_err(|err| UsageError(format!("{err:#}")).into())
+}
 
-    for attr in item_attributes(item) {
-        let attr_range = span_range(attr.span(), line_starts, src.len());
-        if attr_range.start < range.start {
-            range.start = attr_range.start;
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            if err.chain().any(|cause| cause.is::<UsageError>()) {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::from(EXIT_ERROR)
+            }
         }
     }
+}
 
-    range.start = range.start.min(range.end);
-
-    src[range].trim_end().to_string()
+/// Counts the items whose position among the items differs between the
+/// source and the output. `moves` is in output order.
+fn moved_count(moves: &[ItemMove]) -> usize {
+    let mut source_order: Vec<_> = (0..moves.len()).collect();
+    source_order.sort_by_key(|&index| moves[index].original_line);
+    source_order
+        .iter()
+        .enumerate()
+        .filter(|(position, index)| position != *index)
+        .count()
 }
 
-fn line_start_offsets(src: &str) -> Vec<usize> {
-    let mut starts = Vec::with_capacity(src.len() / 32 + 2);
-    starts.push(0);
-    for (idx, ch) in src.char_indices() {
-        if ch == '\n' {
-            let next = idx + ch.len_utf8();
-            starts.push(next);
-        }
-    }
-    if *starts.last().unwrap_or(&0) != src.len() {
-        starts.push(src.len());
+fn parse_line_range(text: &str) -> Result<RangeInclusive<usize>> {
+    let (start, end) = text
+        .split_once(':')
+        .context("expected a line range such as 10:20")?;
+    let start: usize = start.parse().context("invalid start line")?;
+    let end: usize = end.parse().context("invalid end line")?;
+    if start == 0 || end < start {
+        bail!("expected 1-based lines with START no greater than END");
     }
-    starts
+    Ok(start..=end)
 }
 
-fn main() -> Result<()> {
-    let mut raw_args: Vec<String> = std::env::args().collect();
-    if raw_args.len() > 1 && raw_args[1] == "refmt" {
-        raw_args.remove(1);
+/// Adds the file at `path`, of `size` bytes, to `files` unless it was seen
+/// before or is larger than `--max-file-size`.
+fn push_file(
+    path: PathBuf,
+    size: u64,
+    files: &mut Vec<PathBuf>,
+    seen: &mut HashSet<PathBuf>,
+    walk: &Walk,
+) {
+    // Deduplicate by the physical file, but keep the path as the user
+    // reached it for output.
+    if !seen.insert(file_key(&path)) {
+        return;
     }
-    let args = Args::parse_from(raw_args);
-
-    let paths = if args.paths.is_empty() {
-        vec![PathBuf::from(".")]
-    } else {
-        args.paths
-    };
-
-    let files = collect_input_files(paths)?;
-
-    for path in files {
-        reorder_file(&path).with_context(|| format!("refmt {}", path.display()))?;
+    // Oversized files still count as seen, so skipping them is not taken for
+    // finding no files at all.
+    if walk.max_file_size.is_some_and(|max| size > max) {
+        if walk.verbose {
+            eprintln!("skipped {} of {size} bytes", path.display());
+        }
+        return;
     }
-
-    Ok(())
+    files.push(path);
 }
 
-fn push_file(path: PathBuf, files: &mut Vec<PathBuf>, seen: &mut HashSet<PathBuf>) {
-    if seen.insert(path.clone()) {
-        files.push(path);
+/// Reads the paths listed in `list`, or on stdin if it is `-`, separated by
+/// NUL bytes or by newlines. Empty entries are ignored.
+fn read_file_list(list: &Path, null: bool) -> Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    if list == Path::new("-") {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("read file list from stdin")?;
+    } else {
+        bytes = fs::read(list).with_context(|| format!("read file list {}", list.display()))?;
     }
+    let text = String::from_utf8(bytes)
+        .map_err(|_| UsageError(format!("file list {} is not valid UTF-8", list.display())))?;
+    Ok(split_file_list(&text, null))
 }
 
-fn reorder_file(path: &Path) -> Result<()> {
+fn reorder_file(path: &Path, options: &Options, config: &Config) -> Result<Report> {
     let src = fs::read_to_string(path).with_context(|| format!("read file {}", path.display()))?;
-    let mut file: File =
-        syn::parse_file(&src).with_context(|| format!("parse {}", path.display()))?;
-    let line_starts = line_start_offsets(&src);
-
-    let shebang = file.shebang.take();
-    let crate_attrs = std::mem::take(&mut file.attrs);
-
-    let (struct_enum_items, rest_items): (Vec<_>, Vec<_>) = file
-        .items
-        .into_iter()
-        .partition(|item| matches!(item, Item::Struct(_) | Item::Enum(_) | Item::Union(_)));
+    let mut config = config.clone();
+    if options.editorconfig {
+        editorconfig::apply(path, &mut config)?;
+    }
+    let config = &config;
+    let unchanged = |status, moves| {
+        let output = match options.mode {
+            Mode::Stdout => src.clone(),
+            _ => String::new(),
+        };
+        // The mirror tree gets every file, not only those that change.
+        if let Some(target_dir) = &options.target_dir
+            && options.mode == Mode::Write
+        {
+            write_mirrored(path, target_dir, &src)?;
+        }
+        Ok(Report {
+            moves,
+            output,
+            status,
+            warnings: Vec::new(),
+        })
+    };
+    if is_generated(src.trim_start_matches('\u{feff}'), config) {
+        return unchanged(FileStatus::Generated, Vec::new());
+    }
+    let out = match reorder_source_with(&src, config) {
+        Ok(out) => out,
+        // Files that do not parse are left alone with a warning unless
+        // `--fail-on-parse-error` asks for strictness. Under `--force-partial`
+        // this means not even the leading items parse.
+        Err(err)
+            if !options.fail_on_parse_error && (config.force_partial || err.is::<syn::Error>()) =>
+        {
+            let mut report = unchanged(FileStatus::Unparseable, Vec::new())?;
+            if options.verbosity > Verbosity::Quiet {
+                report
+                    .warnings
+                    .push(format!("skipping {}: {err}", path.display()));
+            }
+            return Ok(report);
+        }
+        Err(err) => return Err(err).with_context(|| format!("parse {}", path.display())),
+    };
+    // `-v` names the impls that `--impls-with-types` could not place.
+    let report =
+        options.report || (config.impls_with_types && options.verbosity >= Verbosity::Verbose);
+    // Finding the moves reorders the file again, so it is only done when
+    // they are reported.
+    let moves = if report {
+        match file_moves(&src, config) {
+            Ok(moves) => moves,
+            Err(_) if config.force_partial => Vec::new(),
+            Err(err) => return Err(err).with_context(|| format!("parse {}", path.display())),
+        }
+    } else {
+        Vec::new()
+    };
 
-    let (fn_items, other_items): (Vec<_>, Vec<_>) = rest_items
-        .into_iter()
-        .partition(|item| matches!(item, Item::Fn(_)));
+    if options.mode == Mode::Explain {
+        let status = if out == src {
+            FileStatus::Unchanged
+        } else {
+            FileStatus::Changed
+        };
+        return Ok(Report {
+            moves,
+            output: explain(path, &src, config)?,
+            status,
+            warnings: Vec::new(),
+        });
+    }
+    if out == src {
+        return unchanged(FileStatus::Unchanged, moves);
+    }
+    if options.verify {
+        verify_idempotent(path, &out, &|src| reorder_source_with(src, config))?;
+    }
+    let warnings = check_comments(
+        &path.display().to_string(),
+        &src,
+        &out,
+        options.deny_comment_loss,
+        options.verbosity,
+    )?
+    .into_iter()
+    .collect();
+
+    let mut output = String::new();
+    match options.mode {
+        Mode::Check | Mode::DiffStat | Mode::Explain | Mode::List => {}
+        Mode::Diff => {
+            let path = path.display().to_string();
+            output = diff::unified_diff(&path, &src, &out, options.color);
+        }
+        Mode::Stdout => output = out,
+        Mode::Write => {
+            if let Some(target_dir) = &options.target_dir {
+                write_mirrored(path, target_dir, &out)?;
+            } else {
+                if options.backup {
+                    write_backup(path, &src, options.force)?;
+                }
+                write_reordered(path, &out, config.force_partial)?;
+            }
+        }
+    }
 
-    let sorted_struct_enums = sort_by_usage(struct_enum_items, &src, &line_starts);
+    Ok(Report {
+        moves,
+        output,
+        status: FileStatus::Changed,
+        warnings,
+    })
+}
 
-    let mut sorted_fn_items = fn_items;
-    sorted_fn_items.sort_by(|a, b| {
-        fn_visibility_rank(a)
-            .cmp(&fn_visibility_rank(b))
-            .then_with(|| fn_item_name(a).cmp(&fn_item_name(b)))
+/// Reorders `files` on up to `jobs` threads, each with the configuration in
+/// `configs` at its index in `file_configs`, returning results in input order.
+///
+/// Unless `continue_on_error` is set, files not yet started when one fails are
+/// skipped and their result is `None`.
+fn reorder_files(
+    files: &[PathBuf],
+    options: &Options,
+    configs: &[Config],
+    file_configs: &[usize],
+    jobs: usize,
+) -> Vec<Option<Result<Report>>> {
+    let next = AtomicUsize::new(0);
+    let processed = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut results: Vec<_> = files.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(files.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while !failed.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = files.get(index) else { break };
+                        let config = &configs[file_configs[index]];
+                        let result = reorder_file(path, options, config);
+                        if result.is_err() && !options.continue_on_error {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        let count = processed.fetch_add(1, Ordering::Relaxed) + 1;
+                        if options.progress {
+                            eprint!("\r\x1b[2Kprocessed {count}/{} file(s)", files.len());
+                        }
+                        done.push((index, result));
+                    }
+                    done
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (index, result) in worker.join().expect("worker thread panicked") {
+                results[index] = Some(result);
+            }
+        }
     });
+    // The progress line is erased before the main thread prints the warnings
+    // and summary.
+    if options.progress {
+        eprint!("\r\x1b[2K");
+    }
 
-    let type_order: Vec<String> = sorted_struct_enums
-        .iter()
-        .filter_map(|item| item_name(item))
-        .collect();
+    results
+}
 
-    let mut buckets: Vec<Vec<String>> = vec![Vec::new(); 12];
-    for item in other_items.into_iter() {
-        let cat = category(&item);
-        let snippet = item_snippet(&item, &src, &line_starts);
-        buckets[cat].push(snippet);
+/// Reorders the source on stdin, returning whether it changed. `--check`
+/// only reports a change and `--diff` prints it, while other modes print the
+/// reordered source.
+fn reorder_stdin(
+    config: &Config,
+    range: Option<RangeInclusive<usize>>,
+    mode: Mode,
+    color: bool,
+    deny_comment_loss: bool,
+    verbosity: Verbosity,
+) -> Result<bool> {
+    let mut src = String::new();
+    io::stdin()
+        .read_to_string(&mut src)
+        .context("read source from stdin")?;
+    let out = match range {
+        Some(lines) => reorder_range(&src, config, lines),
+        None => reorder_source_with(&src, config),
     }
-
-    for item in sorted_struct_enums.into_iter() {
-        let snippet = item_snippet(&item, &src, &line_starts);
-        buckets[8].push(snippet);
+    .context("parse stdin")?;
+    match check_comments("stdin", &src, &out, deny_comment_loss, verbosity) {
+        Ok(Some(warning)) => eprintln!("warning: {warning}"),
+        Ok(None) => {}
+        Err(err) => {
+            // An editor piping its buffer through refmt gets it back as it
+            // was rather than empty.
+            if !matches!(mode, Mode::Check | Mode::Diff) {
+                io::stdout()
+                    .write_all(src.as_bytes())
+                    .context("write source to stdout")?;
+            }
+            return Err(err);
+        }
     }
+    let changed = out != src;
+    let output = match mode {
+        Mode::Check => {
+            if changed && verbosity > Verbosity::Quiet {
+                eprintln!("would reorder stdin");
+            }
+            String::new()
+        }
+        Mode::Diff if changed => diff::unified_diff("stdin", &src, &out, color),
+        Mode::Diff => String::new(),
+        _ => out,
+    };
+    io::stdout()
+        .write_all(output.as_bytes())
+        .context("write result to stdout")?;
+    Ok(changed)
+}
 
-    for item in sorted_fn_items.into_iter() {
-        let snippet = item_snippet(&item, &src, &line_starts);
-        buckets[10].push(snippet);
+/// Runs the tool, returning [`ExitCode::FAILURE`] when files are out of order
+/// in `--check` and `--diff` modes.
+fn run() -> Result<ExitCode> {
+    let mut raw_args: Vec<String> = std::env::args().collect();
+    // Cargo passes the subcommand name along when invoked as `cargo refmt`.
+    if raw_args.len() > 1 && matches!(raw_args[1].as_str(), "refmt" | "reorder") {
+        raw_args.remove(1);
     }
+    let args = match Args::try_parse_from(raw_args) {
+        Ok(args) => args,
+        Err(err) => {
+            // `--help` and `--version` are reported as errors too.
+            let _ = err.print();
+            return Ok(if err.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::SUCCESS
+            });
+        }
+    };
 
-    let mut out = String::new();
-    if let Some(sb) = shebang {
-        out.push_str(&sb);
-        out.push('\n');
+    let mut config = match &args.config {
+        Some(path) => load_config(path)?,
+        None => Config::default(),
+    };
+    apply_args(&mut config, &args);
+    // Discovered configurations are kept after `config`, which applies to
+    // files without one of their own, and looked up through `config_dirs`.
+    let mut configs = vec![config];
+    let mut config_dirs = HashMap::new();
+
+    let verbosity = match (args.quiet, args.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Trace,
+    };
+    // `--diff` already fails when files would change, so `--check --diff`
+    // prints the diffs as well.
+    let mode = if args.diff {
+        Mode::Diff
+    } else if args.check {
+        Mode::Check
+    } else if args.diff_stat {
+        Mode::DiffStat
+    } else if args.explain {
+        Mode::Explain
+    } else if args.list_changed {
+        Mode::List
+    } else if args.stdout {
+        Mode::Stdout
+    } else {
+        Mode::Write
+    };
+    let color = match args.color {
+        Color::Always => true,
+        Color::Auto => io::stdout().is_terminal(),
+        Color::Never => false,
+    };
+    if args.stdin || args.paths == [PathBuf::from("-")] {
+        let mut index = 0;
+        if args.config_discover {
+            let dir = std::env::current_dir().context("read current directory")?;
+            index = discover_config(&dir, &mut config_dirs, &mut configs, &args)?;
+        }
+        let changed = reorder_stdin(
+            &configs[index],
+            args.range,
+            mode,
+            color,
+            args.deny_comment_loss,
+            verbosity,
+        )?;
+        return Ok(if changed && matches!(mode, Mode::Check | Mode::Diff) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
     }
-    if !crate_attrs.is_empty() {
-        let header = header_to_string(&crate_attrs, &src, &line_starts);
-        out.push_str(header.trim_end());
-        out.push_str("\n\n");
+    if args.range.is_some() {
+        return Err(UsageError("--range requires reading from stdin".to_string()).into());
     }
 
-    let mut wrote_any = !out.is_empty();
-
-    for (idx, mut bucket) in buckets.into_iter().enumerate() {
-        if bucket.is_empty() {
-            continue;
+    let walk = Walk {
+        depth: args.depth,
+        exclude: args.exclude.clone(),
+        follow_mods: args.follow_mods,
+        include_hidden: args.include_hidden,
+        max_file_size: args.max_file_size,
+        respect_gitignore: !args.no_gitignore,
+        verbose: verbosity >= Verbosity::Verbose,
+    };
+    let mut files = if args.staged {
+        staged_files(&walk)?
+    } else if let Some(list) = &args.files_from {
+        collect_input_files(read_file_list(list, args.null)?, &walk)?
+    } else if args.paths.is_empty() {
+        collect_input_files(vec![default_path()?], &walk)?
+    } else {
+        collect_input_files(args.paths.clone(), &walk)?
+    };
+    // Diffs come in path order, so a review bot posts the same patch
+    // whatever order the paths were given in.
+    if mode == Mode::Diff {
+        files.sort();
+    }
+    let jobs = args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let total = files.len();
+    let options = Options {
+        backup: args.backup,
+        color,
+        continue_on_error: args.continue_on_error,
+        deny_comment_loss: args.deny_comment_loss,
+        editorconfig: !args.no_editorconfig,
+        fail_on_parse_error: args.fail_on_parse_error,
+        force: args.force,
+        mode,
+        progress: verbosity > Verbosity::Quiet && io::stderr().is_terminal(),
+        report: args.report_json.is_some() || args.diff_stat || verbosity >= Verbosity::Trace,
+        target_dir: args.target_dir.clone(),
+        verbosity,
+        verify: args.verify,
+    };
+    let mut file_configs = vec![0; files.len()];
+    if args.config_discover {
+        for (path, index) in files.iter().zip(&mut file_configs) {
+            let dir = std::path::absolute(path)
+                .with_context(|| format!("resolve path {}", path.display()))?;
+            let dir = dir.parent().unwrap_or(Path::new("/"));
+            *index = discover_config(dir, &mut config_dirs, &mut configs, &args)?;
         }
+    }
+    let results = reorder_files(&files, &options, &configs, &file_configs, jobs);
+    let mut unsorted = Vec::new();
+    let mut errors = Vec::new();
+    let mut reported = Vec::new();
+    let mut stats = Vec::new();
+    let mut unparseable = 0;
+
+    for (path, result) in files.into_iter().zip(results) {
+        // Files are only skipped once an earlier file has failed.
+        let Some(result) = result else { break };
+        let report = match result.with_context(|| format!("refmt {}", path.display())) {
+            Ok(report) => report,
+            Err(err) if args.continue_on_error => {
+                eprintln!("error: {err:#}");
+                errors.push(err);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
 
-        if idx == 9 {
-            bucket.sort_by(|a, b| {
-                let name_a = impl_type_name(a);
-                let name_b = impl_type_name(b);
-                let pos_a = type_order.iter().position(|n| n == &name_a);
-                let pos_b = type_order.iter().position(|n| n == &name_b);
-                match (pos_a, pos_b) {
-                    (Some(i), Some(j)) => i.cmp(&j),
-                    (Some(_), None) => std::cmp::Ordering::Less,
-                    (None, Some(_)) => std::cmp::Ordering::Greater,
-                    (None, None) => std::cmp::Ordering::Greater,
-                }
-            });
-        } else if idx != 8 && idx != 10 {
-            bucket.sort();
+        for warning in &report.warnings {
+            eprintln!("warning: {warning}");
         }
-
-        if wrote_any && idx != 0 {
-            while !out.ends_with("\n\n") {
-                out.push('\n');
+        if mode == Mode::Stdout && total > 1 {
+            println!("// === {} ===", path.display());
+        }
+        print!("{}", report.output);
+        if verbosity >= Verbosity::Trace {
+            eprintln!("scanned {}", path.display());
+            for item in &report.moves {
+                eprintln!(
+                    "  {} {} at line {} -> line {}",
+                    item.category.name(),
+                    item.name.as_deref().unwrap_or("(unnamed)"),
+                    item.original_line,
+                    item.new_line
+                );
             }
         }
-        wrote_any = true;
-
-        let extra_blank = blank_lines_after(idx);
-
-        let bucket_len = bucket.len();
-        for (i, item) in bucket.into_iter().enumerate() {
-            out.push_str(item.trim_end_matches('\n'));
-            out.push('\n');
-            if i + 1 < bucket_len {
-                for _ in 0..extra_blank {
-                    out.push('\n');
+        if mode == Mode::DiffStat && report.status == FileStatus::Changed {
+            stats.push((path.display().to_string(), moved_count(&report.moves)));
+        }
+        if verbosity >= Verbosity::Verbose {
+            for item in &report.moves {
+                if let Some(self_type) = &item.unmatched_self_type {
+                    eprintln!(
+                        "{}:{}: impl for `{self_type}` matches no local struct, enum or union",
+                        path.display(),
+                        item.original_line
+                    );
                 }
             }
         }
+        match report.status {
+            FileStatus::Changed => unsorted.push(path.clone()),
+            FileStatus::Generated if verbosity >= Verbosity::Verbose => {
+                eprintln!("skipped generated file {}", path.display());
+            }
+            FileStatus::Unchanged if verbosity >= Verbosity::Verbose => {
+                eprintln!("unchanged {}", path.display());
+            }
+            FileStatus::Unparseable => unparseable += 1,
+            FileStatus::Generated | FileStatus::Unchanged => {}
+        }
+        if args.report_json.is_some() {
+            reported.push((path, report.status, report.moves));
+        }
     }
 
-    while out.ends_with("\n\n\n") {
-        out.pop();
+    if mode == Mode::DiffStat {
+        print!("{}", diff_stat(&stats));
     }
-    let src_has_trailing_newline = src.ends_with('\n');
-    let out_has_trailing_newline = out.ends_with('\n');
-    if src_has_trailing_newline && !out_has_trailing_newline {
-        out.push('\n');
-    } else if !src_has_trailing_newline && out_has_trailing_newline {
-        out.pop();
+    if !errors.is_empty() {
+        eprintln!("failed to reorder {} file(s)", errors.len());
     }
-
-    if out != src {
-        fs::write(path, out)?;
+    if let Some(report_path) = &args.report_json {
+        fs::write(report_path, json_report(&reported))
+            .with_context(|| format!("write report {}", report_path.display()))?;
     }
 
-    Ok(())
-}
-
-fn sort_by_usage(items: Vec<Item>, src: &str, _line_starts: &[usize]) -> Vec<Item> {
-    if items.is_empty() {
-        return items;
-    }
-
-    let mut name_to_item: HashMap<String, Item> = HashMap::new();
-    let mut names: Vec<String> = Vec::new();
-
-    for item in &items {
-        let name = item_name(item);
-        if let Some(n) = name {
-            name_to_item.insert(n.clone(), item.clone());
-            names.push(n);
+    match mode {
+        Mode::Check | Mode::Write if verbosity > Verbosity::Quiet => {
+            let verb = if mode == Mode::Write {
+                "reordered"
+            } else {
+                "would reorder"
+            };
+            for path in &unsorted {
+                eprintln!("{verb} {}", path.display());
+            }
+        }
+        Mode::List => {
+            for path in &unsorted {
+                println!("{}", path.display());
+            }
         }
+        Mode::Check | Mode::Diff | Mode::DiffStat | Mode::Explain | Mode::Stdout | Mode::Write => {}
+    }
+    match (verbosity, mode) {
+        (Verbosity::Quiet, _) => {}
+        (_, Mode::Write) => eprintln!("reordered {} of {total} file(s)", unsorted.len()),
+        _ => eprintln!("{} of {total} file(s) would be reordered", unsorted.len()),
+    }
+    if unparseable > 0 && verbosity > Verbosity::Quiet {
+        eprintln!("skipped {unparseable} file(s) that do not parse");
     }
 
-    let refs = find_references(&names, src);
+    if !errors.is_empty() {
+        return Ok(ExitCode::from(EXIT_ERROR));
+    }
 
-    let mut outgoing_counts: HashMap<String, usize> = HashMap::new();
-    for name in &names {
-        outgoing_counts.insert(name.clone(), refs.get(name).map(|v| v.len()).unwrap_or(0));
+    if matches!(mode, Mode::Check | Mode::Diff | Mode::DiffStat) && !unsorted.is_empty() {
+        return Ok(ExitCode::FAILURE);
     }
 
-    let mut name_count: Vec<(String, usize)> = names
-        .iter()
-        .map(|n| (n.clone(), *outgoing_counts.get(n).unwrap_or(&0)))
-        .collect();
+    Ok(ExitCode::SUCCESS)
+}
 
-    name_count.sort_by(|a, b| b.1.cmp(&a.1));
+fn split_file_list(text: &str, null: bool) -> Vec<PathBuf> {
+    let entries: Vec<&str> = if null {
+        text.split('\0').collect()
+    } else {
+        text.lines().collect()
+    };
+    entries
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
 
-    let mut sorted: Vec<Item> = Vec::new();
-    for (name, _) in name_count {
-        if let Some(item) = name_to_item.get(&name) {
-            sorted.push(item.clone());
+/// Returns the added, copied or modified Rust files in the git index of the
+/// repository containing the working directory.
+fn staged_files(walk: &Walk) -> Result<Vec<PathBuf>> {
+    let git = |args: &[&str]| -> Result<String> {
+        let output = Command::new("git").args(args).output().context("run git")?;
+        if !output.status.success() {
+            bail!(
+                "--staged requires a git repository: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout).context("read git output")
+    };
+
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim_end());
+    let names = git(&["diff", "--cached", "--name-only", "--diff-filter=ACM", "-z"])?;
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for name in names.split('\0').filter(|name| !name.is_empty()) {
+        let path = root.join(name);
+        if is_rust_file(&path) && !is_excluded(&walk.exclude, &path, Path::new(name)) {
+            let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+            push_file(path, size, &mut files, &mut seen, walk);
         }
     }
 
-    sorted
+    Ok(files)
 }
 
-fn span_range(
-    span: proc_macro2::Span,
-    line_starts: &[usize],
-    src_len: usize,
-) -> std::ops::Range<usize> {
-    let start = span.start();
-    let end = span.end();
+/// Turns a Windows extended-length path such as `\\?\C:\src\lib.rs` or
+/// `\\?\UNC\server\share\lib.rs` into its ordinary form.
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{rest}"))
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path
+    }
+}
 
-    let start_line_index = start.line.saturating_sub(1);
-    let end_line_index = end.line.saturating_sub(1);
+/// Fails unless reordering `out`, the reordered text of `path`, with
+/// `reorder` leaves it unchanged.
+fn verify_idempotent(
+    path: &Path,
+    out: &str,
+    reorder: &dyn Fn(&str) -> Result<String>,
+) -> Result<()> {
+    let again = reorder(out).with_context(|| format!("verify {}", path.display()))?;
+    if again != out {
+        let diff = diff::unified_diff(&path.display().to_string(), out, &again, false);
+        bail!(
+            "verification failed for {}: reordering the output changes it again\n{diff}",
+            path.display()
+        );
+    }
+    Ok(())
+}
 
-    let start_line_base = line_starts
-        .get(start_line_index)
-        .copied()
-        .unwrap_or(src_len);
-    let end_line_base = line_starts.get(end_line_index).copied().unwrap_or(src_len);
+fn write_atomically(path: &Path, tmp_path: &Path, out: &str) -> Result<()> {
+    let permissions = fs::metadata(path)
+        .with_context(|| format!("inspect metadata for {}", path.display()))?
+        .permissions();
+    // The temporary file would replace it regardless, so a read-only file is
+    // refused here as writing to it directly would be.
+    if permissions.readonly() {
+        bail!("cannot write {}: read-only file", path.display());
+    }
+    fs::write(tmp_path, out).map_err(|err| write_error(path, tmp_path, "write file", err))?;
+    fs::set_permissions(tmp_path, permissions)
+        .map_err(|err| write_error(path, tmp_path, "set permissions on", err))?;
+    fs::rename(tmp_path, path).map_err(|err| write_error(path, path, "replace file", err))
+}
 
-    let mut start_idx = start_line_base.saturating_add(start.column);
-    let mut end_idx = end_line_base.saturating_add(end.column);
+fn write_backup(path: &Path, src: &str, force: bool) -> Result<()> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
 
-    if start_idx > src_len {
-        start_idx = src_len;
-    }
-    if end_idx > src_len {
-        end_idx = src_len;
+    let mut file = if force {
+        fs::File::create(&backup)
+    } else {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
     }
+    .map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => anyhow!(
+            "backup {} already exists; pass --force to overwrite it",
+            backup.display()
+        ),
+        _ => anyhow::Error::new(err).context(format!("create backup {}", backup.display())),
+    })?;
+    file.write_all(src.as_bytes())
+        .with_context(|| format!("write backup {}", backup.display()))
+}
 
-    if start_idx > end_idx {
-        start_idx = end_idx;
+/// Describes the failure of `action` on `target` while writing `path`. Lack
+/// of permission and read-only filesystems are reported against `path`, as
+/// the temporary file next to it means nothing to the user.
+fn write_error(path: &Path, target: &Path, action: &str, err: io::Error) -> anyhow::Error {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => {
+            anyhow!("cannot write {}: permission denied", path.display())
+        }
+        io::ErrorKind::ReadOnlyFilesystem => {
+            anyhow!("cannot write {}: read-only filesystem", path.display())
+        }
+        _ => anyhow::Error::new(err).context(format!("{action} {}", target.display())),
     }
-
-    start_idx..end_idx
 }
 
-fn use_category(use_item: &syn::ItemUse) -> Cat {
-    fn get_first_ident(tree: &syn::UseTree) -> Option<&syn::Ident> {
-        match tree {
-            syn::UseTree::Path(tree) => Some(&tree.ident),
-            syn::UseTree::Group(tree) => tree.items.first().and_then(|t| get_first_ident(t)),
-            syn::UseTree::Name(tree) => Some(&tree.ident),
-            syn::UseTree::Rename(_) | syn::UseTree::Glob(_) => None,
-        }
+/// Writes `text` to the path of `path` relative to the current directory
+/// under `target_dir`, creating the directories on the way.
+fn write_mirrored(path: &Path, target_dir: &Path, text: &str) -> Result<()> {
+    let dir = std::env::current_dir().context("read current directory")?;
+    let absolute =
+        std::path::absolute(path).with_context(|| format!("resolve path {}", path.display()))?;
+    let relative = absolute
+        .strip_prefix(&dir)
+        .ok()
+        .filter(|relative| {
+            relative
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)))
+        })
+        .with_context(|| {
+            format!(
+                "{} is outside the current directory, so --target-dir has no place for it",
+                path.display()
+            )
+        })?;
+    let target = target_dir.join(relative);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("create directory {}", parent.display()))?;
     }
+    fs::write(&target, text).map_err(|err| write_error(&target, &target, "write file", err))
+}
 
-    let ident = match get_first_ident(&use_item.tree) {
-        Some(id) => id,
-        _ => return 1,
-    };
-    let ident_str = ident.to_string();
-    if ident_str == "crate" || ident_str == "self" {
-        return 2;
+/// Replaces the contents of `path` with `out`, refusing output that does not
+/// parse unless it comes from `--force-partial`, which keeps the tail that
+/// failed to parse.
+fn write_reordered(path: &Path, out: &str, force_partial: bool) -> Result<()> {
+    if !force_partial && let Err(err) = syn::parse_file(out) {
+        bail!(
+            "refusing to write {}: reordered output does not parse: {err}",
+            path.display()
+        );
     }
-    if is_std_crate(&ident_str) {
-        return 0;
+
+    // Replace the file a symlink points to rather than the link itself.
+    let path =
+        &fs::canonicalize(path).with_context(|| format!("canonicalize path {}", path.display()))?;
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".refmt-{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = write_atomically(path, &tmp_path, out);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
     }
-    1
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    thread_local! {
+        pub(super) static FILE_MOVES_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
     #[test]
-    fn test_is_rust_file() {
-        assert!(is_rust_file(Path::new("foo.rs")));
-        assert!(is_rust_file(Path::new("foo.RS")));
-        assert!(!is_rust_file(Path::new("foo.Rust")));
-        assert!(!is_rust_file(Path::new("foo.txt")));
-        assert!(!is_rust_file(Path::new("foo")));
-        assert!(!is_rust_file(Path::new("foo.rs.txt")));
+    fn test_verify_idempotent() {
+        let path = Path::new("src/lib.rs");
+        assert!(verify_idempotent(path, "fn a() {}\n", &|src| Ok(src.to_string())).is_ok());
+
+        // Appends another item on every run, so it never settles.
+        let err = verify_idempotent(path, "fn a() {}\n", &|src| {
+            Ok(format!("{src}fn b() {{}}\n"))
+        })
+        .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(
+            message.contains("verification failed for src/lib.rs"),
+            "{message}"
+        );
+        assert!(message.contains("+fn b() {}"), "{message}");
     }
 
     #[test]
-    fn test_line_start_offsets() {
-        let src = "line1\nline2\nline3";
-        let starts = line_start_offsets(src);
-        assert_eq!(starts, vec![0, 6, 12, 17]);
+    fn test_split_file_list() {
+        assert_eq!(
+            split_file_list("src/a b.rs\0src/c.rs\0", true),
+            [PathBuf::from("src/a b.rs"), PathBuf::from("src/c.rs")]
+        );
+        assert_eq!(
+            split_file_list("src/a.rs\r\n\nsrc/b.rs\n", false),
+            [PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]
+        );
+        assert_eq!(
+            split_file_list("a.rs\nb.rs", true),
+            [PathBuf::from("a.rs\nb.rs")]
+        );
     }
 
     #[test]
-    fn test_line_start_offsets_empty() {
-        let src = "";
-        let starts = line_start_offsets(src);
-        assert_eq!(starts, vec![0]);
+    fn test_moves_only_found_when_reported() {
+        let path = std::env::temp_dir().join(format!("refmt-moves-{}.rs", std::process::id()));
+        fs::write(&path, "fn b() {}\n\nfn a() {}\n").unwrap();
+        let mut options = Options {
+            backup: false,
+            color: false,
+            continue_on_error: false,
+            deny_comment_loss: false,
+            editorconfig: false,
+            fail_on_parse_error: false,
+            force: false,
+            mode: Mode::Check,
+            progress: false,
+            report: false,
+            target_dir: None,
+            verbosity: Verbosity::Normal,
+            verify: false,
+        };
+        let calls = || FILE_MOVES_CALLS.with(std::cell::Cell::get);
+
+        let report = reorder_file(&path, &options, &Config::default()).unwrap();
+        assert!(report.status == FileStatus::Changed);
+        assert!(report.moves.is_empty());
+        assert_eq!(calls(), 0);
+
+        options.report = true;
+        let report = reorder_file(&path, &options, &Config::default()).unwrap();
+        assert_eq!(report.moves.len(), 2);
+        assert_eq!(calls(), 1);
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_line_start_offsets_single_line() {
-        let src = "hello";
-        let starts = line_start_offsets(src);
-        assert_eq!(starts, vec![0, 5]);
+    fn test_write_error() {
+        let path = Path::new("src/lib.rs");
+        let tmp_path = Path::new("src/.lib.rs.refmt-1.tmp");
+        let message = |kind| {
+            format!(
+                "{:#}",
+                write_error(path, tmp_path, "write file", io::Error::from(kind))
+            )
+        };
+
+        assert_eq!(
+            message(io::ErrorKind::PermissionDenied),
+            "cannot write src/lib.rs: permission denied"
+        );
+        assert_eq!(
+            message(io::ErrorKind::ReadOnlyFilesystem),
+            "cannot write src/lib.rs: read-only filesystem"
+        );
+        assert!(
+            message(io::ErrorKind::StorageFull).starts_with("write file src/.lib.rs.refmt-1.tmp: ")
+        );
     }
 
     #[test]
-    fn test_blank_lines_after() {
-        assert_eq!(blank_lines_after(0), 0);
-        assert_eq!(blank_lines_after(1), 0);
-        assert_eq!(blank_lines_after(2), 0);
-        assert_eq!(blank_lines_after(3), 0);
-        assert_eq!(blank_lines_after(4), 0);
-        assert_eq!(blank_lines_after(5), 0);
-        assert_eq!(blank_lines_after(6), 0);
-        assert_eq!(blank_lines_after(7), 1);
-        assert_eq!(blank_lines_after(8), 1);
-        assert_eq!(blank_lines_after(9), 1);
-        assert_eq!(blank_lines_after(10), 1);
-        assert_eq!(blank_lines_after(11), 1);
+    fn test_write_reordered_rejects_unparseable_output() {
+        let path = std::env::temp_dir().join(format!("refmt-guard-{}.rs", std::process::id()));
+        fs::write(&path, "fn run() {}\n").unwrap();
+
+        let err = write_reordered(&path, "fn run() {\n", false).unwrap_err();
+
+        assert!(err.to_string().contains("refusing to write"), "got {err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn run() {}\n");
+
+        write_reordered(&path, "fn run() {\n", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn run() {\n");
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_find_item_range_with_braces() {
-        let src = "struct Foo { field: i32 }";
-        let range = find_item_range("Foo", src);
-        assert_eq!(range, Some((7, 25)));
+    fn test_strip_verbatim_prefix() {
+        let strip = |path: &str| strip_verbatim_prefix(PathBuf::from(path));
+        assert_eq!(strip(r"\\?\C:\src\lib.rs"), PathBuf::from(r"C:\src\lib.rs"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\lib.rs"),
+            PathBuf::from(r"\\server\share\lib.rs")
+        );
+        assert_eq!(strip(r"C:\src\lib.rs"), PathBuf::from(r"C:\src\lib.rs"));
+        assert_eq!(strip("src/lib.rs"), PathBuf::from("src/lib.rs"));
     }
 
     #[test]
-    fn test_find_item_range_with_semicolon() {
-        let src = "struct Foo;";
-        let range = find_item_range("Foo", src);
-        assert_eq!(range, Some((7, 11)));
+    fn test_json_string() {
+        assert_eq!(json_string("src/lib.rs"), "\"src/lib.rs\"");
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
     }
 
     #[test]
-    fn test_find_item_range_not_found() {
-        let src = "struct Bar { field: i32 }";
-        let range = find_item_range("Foo", src);
-        assert!(range.is_none());
+    fn test_is_excluded() {
+        let exclude = ["*_generated.rs".to_string(), "src/bindings/".to_string()];
+        let excluded = |path: &str, relative: &str| {
+            is_excluded(&exclude, Path::new(path), Path::new(relative))
+        };
+
+        assert!(excluded("./src/api_generated.rs", "src/api_generated.rs"));
+        assert!(excluded("./src/bindings", "src/bindings"));
+        assert!(excluded("src/bindings", "bindings"));
+        assert!(!excluded("src/lib.rs", "lib.rs"));
+        assert!(!excluded("./other/bindings", "other/bindings"));
+    }
+
+    #[test]
+    fn test_is_rust_file() {
+        assert!(is_rust_file(Path::new("foo.rs")));
+        assert!(is_rust_file(Path::new("foo.RS")));
+        assert!(!is_rust_file(Path::new("foo.Rust")));
+        assert!(!is_rust_file(Path::new("foo.txt")));
+        assert!(!is_rust_file(Path::new("foo")));
+        assert!(!is_rust_file(Path::new("foo.rs.txt")));
     }
 }
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+
+use syn::Item;
+use syn::ext::IdentExt;
+
+/// Resolves the files of the `mod foo;` declarations in the file at `path`,
+/// including those nested in inline modules, along with whether each was
+/// resolved through a `#[path]` attribute.
+///
+/// Each declaration resolves to its `#[path = "..."]` attribute when present,
+/// and otherwise to `foo.rs` or `foo/mod.rs`. Declarations whose file does not
+/// exist are skipped, as are all declarations if `src` does not parse.
+///
+/// As in rustc, a file loaded through `#[path]` owns its directory like a
+/// `mod.rs` file does, which callers indicate with `via_path`.
+pub fn declared_files(path: &Path, src: &str, via_path: bool) -> Vec<(PathBuf, bool)> {
+    let Ok(file) = syn::parse_file(src.trim_start_matches('\u{feff}')) else {
+        return Vec::new();
+    };
+
+    let file_dir = path.parent().unwrap_or(Path::new(""));
+    // Only `mod.rs`, crate roots and `#[path]` files own the directory they
+    // are in; other files look for their submodules in a directory named
+    // after themselves.
+    let dir = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if !via_path && !matches!(stem, "lib" | "main" | "mod") => file_dir.join(stem),
+        _ => file_dir.to_path_buf(),
+    };
+
+    let mut files = Vec::new();
+    collect_declared(&file.items, file_dir, &dir, false, &mut files);
+    files
+}
+
+fn collect_declared(
+    items: &[Item],
+    file_dir: &Path,
+    dir: &Path,
+    inline: bool,
+    files: &mut Vec<(PathBuf, bool)>,
+) {
+    for item in items {
+        let Item::Mod(item_mod) = item else { continue };
+        let name = item_mod.ident.unraw().to_string();
+        let path_attr = path_attribute(&item_mod.attrs);
+
+        match &item_mod.content {
+            Some((_, items)) => {
+                let child = dir.join(path_attr.as_deref().unwrap_or(&name));
+                collect_declared(items, file_dir, &child, true, files);
+            }
+            None => {
+                let via_path = path_attr.is_some();
+                let file = match path_attr {
+                    // Outside inline modules, `#[path]` is relative to the
+                    // directory of the declaring file itself.
+                    Some(path) if inline => dir.join(path),
+                    Some(path) => file_dir.join(path),
+                    None => {
+                        let flat = dir.join(format!("{name}.rs"));
+                        if flat.is_file() {
+                            flat
+                        } else {
+                            dir.join(&name).join("mod.rs")
+                        }
+                    }
+                };
+                if file.is_file() {
+                    files.push((file, via_path));
+                }
+            }
+        }
+    }
+}
+
+fn path_attribute(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| {
+        let syn::Meta::NameValue(meta) = &attr.meta else {
+            return None;
+        };
+        if !meta.path.is_ident("path") {
+            return None;
+        }
+        match &meta.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => Some(lit.value()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_attribute() {
+        let file =
+            syn::parse_file("#[path = \"other.rs\"]\n#[cfg(unix)]\nmod foo;\nmod bar;\n").unwrap();
+        let attrs = |index: usize| match &file.items[index] {
+            Item::Mod(item_mod) => item_mod.attrs.clone(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(path_attribute(&attrs(0)).as_deref(), Some("other.rs"));
+        assert_eq!(path_attribute(&attrs(1)), None);
+    }
+}
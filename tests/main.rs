@@ -1,6 +1,7 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
 
 fn cargo_bin() -> std::path::PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -9,6 +10,39 @@ fn cargo_bin() -> std::path::PathBuf {
         .join("cargo-refmt")
 }
 
+fn run_refmt(args: &[&str]) -> Output {
+    let bin_path = cargo_bin();
+    Command::new(&bin_path)
+        .args(args)
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run reorder at {:?}: {}", bin_path, e))
+}
+
+fn run_refmt_stdin(args: &[&str], input: &str) -> Output {
+    let bin_path = cargo_bin();
+    let mut child = Command::new(&bin_path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("failed to run reorder at {:?}: {}", bin_path, e));
+    let written = child
+        .stdin
+        .take()
+        .expect("stdin not captured")
+        .write_all(input.as_bytes());
+    // Arguments rejected up front make the command exit without reading stdin.
+    if let Err(err) = written
+        && err.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        panic!("failed to write stdin: {err}");
+    }
+    child
+        .wait_with_output()
+        .expect("failed to wait for reorder")
+}
+
 fn run_reorder(path: &Path) -> String {
     let bin_path = cargo_bin();
     let output = Command::new(&bin_path)
@@ -23,6 +57,86 @@ fn run_reorder(path: &Path) -> String {
     fs::read_to_string(path).expect("failed to read file")
 }
 
+fn test_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/regression");
+    fs::create_dir_all(&dir).expect("failed to create test dir");
+    dir
+}
+
+fn tmp_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("failed to create test dir");
+    dir
+}
+
+#[test]
+fn test_atomic_write_leaves_no_temp_file() {
+    let dir = tmp_dir("atomic_write");
+    let path = dir.join("lib.rs");
+    fs::write(&path, "pub fn run() {}\n\nuse std::fs;\n").expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(result, "use std::fs;\n\npub fn run() {}\n");
+    let entries: Vec<_> = fs::read_dir(&dir)
+        .expect("failed to read test dir")
+        .map(|entry| entry.expect("failed to read entry").file_name())
+        .collect();
+    assert_eq!(entries, ["lib.rs"], "temporary file left behind");
+}
+
+#[test]
+fn test_attributes_separated_by_blank_lines_keep_their_order() {
+    let input = "fn z() {}\n\n/// Doc.\n\n#[inline]\nfn a() {}\n\n#[cfg(unix)]\n/// Doc.\n\n#[must_use]\n/// More.\n\n#[allow(dead_code)]\nfn b() {}\n";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "/// Doc.\n\n#[inline]\nfn a() {}\n\n#[cfg(unix)]\n/// Doc.\n\n#[must_use]\n/// More.\n\n#[allow(dead_code)]\nfn b() {}\n\nfn z() {}\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+}
+
+#[test]
+fn test_backup_written_before_overwriting() {
+    let dir = tmp_dir("backup");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    fs::write(dir.join("lib.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("sorted.rs"), sorted).expect("failed to write test file");
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--backup", dir_str]);
+
+    assert!(output.status.success());
+    assert_eq!(read("lib.rs"), sorted);
+    assert_eq!(read("lib.rs.bak"), unsorted);
+    assert!(
+        !dir.join("sorted.rs.bak").exists(),
+        "unchanged file backed up"
+    );
+
+    fs::write(dir.join("lib.rs"), unsorted).expect("failed to write test file");
+
+    let output = run_refmt(&["--backup", dir_str]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already exists"), "stderr: {stderr}");
+    assert_eq!(read("lib.rs"), unsorted, "file rewritten without a backup");
+
+    fs::write(dir.join("lib.rs"), "fn b() {}\n\nfn a() {}\n").expect("failed to write test file");
+
+    let output = run_refmt(&["--backup", "--force", dir_str]);
+
+    assert!(output.status.success());
+    assert_eq!(read("lib.rs.bak"), "fn b() {}\n\nfn a() {}\n");
+}
+
 #[test]
 fn test_bare_mod_tests_not_at_bottom() {
     let path = test_dir().join("bare_mod_tests.rs");
@@ -54,203 +168,240 @@ pub fn run() {}
 }
 
 #[test]
-fn test_cfg_test_module_at_bottom() {
-    let path = test_dir().join("cfg_test_module.rs");
+fn test_bom_preserved() {
+    let path = test_dir().join("bom.rs");
     fs::write(
         &path,
-        "\
-#[cfg(test)]
-mod tests {
-    use super::*;
-}
-
-use std::fs;
-
-pub fn run() {}
-",
+        "\u{feff}//! Crate docs.\n\npub fn run() {}\n\nuse std::fs;\n",
     )
     .expect("failed to write test file");
 
     let result = run_reorder(&path);
 
-    let use_pos = result.find("use std::fs").expect("use not found");
-    let fn_pos = result.find("pub fn run").expect("fn not found");
-    let test_pos = result.find("#[cfg(test)]").expect("#[cfg(test)] not found");
-    assert!(
-        test_pos > use_pos,
-        "#[cfg(test)] mod should be after use at {use_pos}, got test at {test_pos}"
-    );
-    assert!(
-        test_pos > fn_pos,
-        "#[cfg(test)] mod should be after fn at {fn_pos}, got test at {test_pos}"
+    assert_eq!(
+        result,
+        "\u{feff}//! Crate docs.\n\nuse std::fs;\n\npub fn run() {}\n"
     );
 }
 
 #[test]
-fn test_constants_no_blank_lines() {
-    let path = test_dir().join("constants.rs");
-    fs::write(
-        &path,
-        "\
-const DEFAULT_MODEL: &str = \"gpt-5.4\";
+fn test_cargo_subcommand_defaults_to_package_src() {
+    let dir = tmp_dir("cargo_subcommand");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::create_dir_all(dir.join("src/nested")).expect("failed to create test dir");
+    fs::create_dir_all(dir.join("examples")).expect("failed to create test dir");
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"demo\"\n")
+        .expect("failed to write manifest");
+    fs::write(dir.join("src/lib.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("examples/demo.rs"), unsorted).expect("failed to write test file");
 
-const EXECUTOR_TURNS: usize = 12;
+    let output = Command::new(cargo_bin())
+        .arg("reorder")
+        .current_dir(dir.join("src/nested"))
+        .output()
+        .expect("failed to run reorder");
 
-const IMPLEMENTATION_RETRY_LIMIT: usize = 3;
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("src/lib.rs"), "use std::fs;\n\npub fn run() {}\n");
+    assert_eq!(read("examples/demo.rs"), unsorted);
+}
 
-const MAX_FINAL_REVIEW_PASSES: usize = 3;
+#[test]
+fn test_category_order_splits_async_fns() {
+    let dir = tmp_dir("split_async_fns");
+    let path = dir.join("lib.rs");
+    let src = "\
+async fn fetch() {}
 
-const WORKFLOW_MAX_CONCURRENCY: usize = 4;
+fn parse() {}
 
-const WORKTREE_DIR: &str = \".mmat-worktrees\";
-",
-    )
-    .expect("failed to write test file");
+pub async fn serve() {}
 
-    let result = run_reorder(&path);
+pub fn main() {}
+";
+    let config = dir.join("reorder.toml");
+    let reorder = |order: &str| {
+        fs::write(&path, src).expect("failed to write test file");
+        fs::write(&config, format!("category_order = [{order}]\n"))
+            .expect("failed to write config");
+        let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "reorder failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        fs::read_to_string(&path).expect("failed to read file")
+    };
 
     assert_eq!(
-        result,
+        reorder("\"fn\""),
         "\
-const DEFAULT_MODEL: &str = \"gpt-5.4\";
-const EXECUTOR_TURNS: usize = 12;
-const IMPLEMENTATION_RETRY_LIMIT: usize = 3;
-const MAX_FINAL_REVIEW_PASSES: usize = 3;
-const WORKFLOW_MAX_CONCURRENCY: usize = 4;
-const WORKTREE_DIR: &str = \".mmat-worktrees\";
-"
-    );
-}
-
-fn test_dir() -> PathBuf {
-    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/regression");
-    fs::create_dir_all(&dir).expect("failed to create test dir");
-    dir
-}
+pub fn main() {}
 
-#[test]
-fn test_fn_visibility_order() {
-    let path = test_dir().join("fn_visibility.rs");
-    fs::write(
-        &path,
-        "\
-fn private_fn() {}
+pub async fn serve() {}
 
-pub(crate) fn crate_fn() {}
+async fn fetch() {}
 
-pub fn public_fn() {}
+fn parse() {}
 ",
-    )
-    .expect("failed to write test file");
+        "async fns are sorted with the other functions unless listed"
+    );
+    assert_eq!(
+        reorder("\"fn\", \"async_fn\""),
+        "\
+pub fn main() {}
 
-    let result = run_reorder(&path);
+fn parse() {}
 
-    let pub_pos = result.find("pub fn public_fn").expect("pub fn not found");
-    let crate_pos = result
-        .find("pub(crate) fn crate_fn")
-        .expect("pub(crate) fn not found");
-    let priv_pos = result.find("fn private_fn").expect("private fn not found");
-    assert!(
-        pub_pos < crate_pos,
-        "pub fn should come before pub(crate) fn: got pub at {pub_pos}, pub(crate) at {crate_pos}"
-    );
-    assert!(
-        crate_pos < priv_pos,
-        "pub(crate) fn should come before private fn: got pub(crate) at {crate_pos}, private at {priv_pos}"
+pub async fn serve() {}
+
+async fn fetch() {}
+"
     );
 }
 
 #[test]
-fn test_impl_order_by_type_order() {
-    let path = test_dir().join("impl_order.rs");
-    fs::write(
-        &path,
+fn test_category_order_splits_statics() {
+    let dir = tmp_dir("split_statics");
+    let path = dir.join("lib.rs");
+    let src = "\
+static mut COUNTER: u32 = 0;
+const LIMIT: u32 = 10;
+static NAME: &str = \"demo\";
+pub const MAX: u32 = 20;
+static mut BUFFER: [u8; 4] = [0; 4];
+pub static VERSION: &str = \"1\";
+";
+    let config = dir.join("reorder.toml");
+    let reorder = |order: &str| {
+        fs::write(&path, src).expect("failed to write test file");
+        fs::write(&config, format!("category_order = [{order}]\n"))
+            .expect("failed to write config");
+        let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "reorder failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        fs::read_to_string(&path).expect("failed to read file")
+    };
+
+    assert_eq!(
+        reorder("\"const\""),
         "\
-pub struct ArtifactId(pub String);
+const LIMIT: u32 = 10;
+pub const MAX: u32 = 20;
+pub static VERSION: &str = \"1\";
+static NAME: &str = \"demo\";
+static mut BUFFER: [u8; 4] = [0; 4];
+static mut COUNTER: u32 = 0;
+",
+        "statics share the const section unless listed"
+    );
+    assert_eq!(
+        reorder("\"const\", \"static\""),
+        "\
+const LIMIT: u32 = 10;
+pub const MAX: u32 = 20;
 
-pub struct TransitionId(pub String);
+pub static VERSION: &str = \"1\";
+static NAME: &str = \"demo\";
+static mut BUFFER: [u8; 4] = [0; 4];
+static mut COUNTER: u32 = 0;
+"
+    );
+    assert_eq!(
+        reorder("\"static_mut\", \"const\", \"static\""),
+        "\
+static mut BUFFER: [u8; 4] = [0; 4];
+static mut COUNTER: u32 = 0;
 
-pub struct ArtifactRef {
-    data: i32,
-}
+const LIMIT: u32 = 10;
+pub const MAX: u32 = 20;
 
-impl ArtifactId {
-    pub fn new() -> Self {
-        Self(String::new())
-    }
+pub static VERSION: &str = \"1\";
+static NAME: &str = \"demo\";
+"
+    );
 }
 
-impl ArtifactRef {
-    pub fn downcast_ref(&self) -> i32 {
-        self.data
-    }
-}
+#[test]
+fn test_category_order_splits_trait_aliases() {
+    let dir = tmp_dir("split_trait_aliases");
+    let path = dir.join("lib.rs");
+    let src = "\
+trait Shared = Send + Sync;
 
-impl Default for ArtifactId {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+fn run() {}
 
-impl TransitionId {
-    pub fn new(id: String) -> Self {
-        Self(id)
-    }
+pub trait Plugin {
+    fn name(&self) -> &str;
 }
-",
-    )
-    .expect("failed to write test file");
-
-    let result = run_reorder(&path);
+";
+    let config = dir.join("reorder.toml");
+    let reorder = |order: &str| {
+        fs::write(&path, src).expect("failed to write test file");
+        fs::write(&config, format!("category_order = [{order}]\n"))
+            .expect("failed to write config");
+        let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "reorder failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        fs::read_to_string(&path).expect("failed to read file")
+    };
 
     assert_eq!(
-        result,
+        reorder("\"trait\", \"fn\""),
         "\
-pub struct ArtifactId(pub String);
-
-pub struct TransitionId(pub String);
-
-pub struct ArtifactRef {
-    data: i32,
+pub trait Plugin {
+    fn name(&self) -> &str;
 }
 
-impl ArtifactId {
-    pub fn new() -> Self {
-        Self(String::new())
-    }
-}
+trait Shared = Send + Sync;
 
-impl Default for ArtifactId {
-    fn default() -> Self {
-        Self::new()
-    }
+fn run() {}
+",
+        "trait aliases share the trait section unless listed"
+    );
+    assert_eq!(
+        reorder("\"trait\", \"fn\", \"trait_alias\""),
+        "\
+pub trait Plugin {
+    fn name(&self) -> &str;
 }
 
-impl TransitionId {
-    pub fn new(id: String) -> Self {
-        Self(id)
-    }
-}
+fn run() {}
 
-impl ArtifactRef {
-    pub fn downcast_ref(&self) -> i32 {
-        self.data
-    }
-}
+trait Shared = Send + Sync;
 "
     );
 }
 
 #[test]
-fn test_import_ordering() {
-    let path = test_dir().join("imports.rs");
+fn test_cfg_test_items_grouped_with_tests() {
+    let path = test_dir().join("cfg_test_items.rs");
     fs::write(
         &path,
         "\
-use uuid::Uuid;
-use std::fs::File;
-use crate::module::Blah;
-use serde::Deserialize;
+#[cfg(test)]
+const FIXTURE: &str = \"input\";
+
+const LIMIT: usize = 4;
+
+#[cfg(test)]
+fn helper() {}
+
+pub fn run() {}
+
+#[cfg(test)]
+mod tests {}
 ",
     )
     .expect("failed to write test file");
@@ -260,27 +411,34 @@ use serde::Deserialize;
     assert_eq!(
         result,
         "\
-use std::fs::File;
+const LIMIT: usize = 4;
 
-use serde::Deserialize;
-use uuid::Uuid;
+pub fn run() {}
 
-use crate::module::Blah;
+#[cfg(test)]
+const FIXTURE: &str = \"input\";
+
+#[cfg(test)]
+mod tests {}
+
+#[cfg(test)]
+fn helper() {}
 "
     );
 }
 
 #[test]
-fn test_mod_after_use_not_at_bottom() {
-    let path = test_dir().join("mod_after_use.rs");
+fn test_cfg_test_module_at_bottom() {
+    let path = test_dir().join("cfg_test_module.rs");
     fs::write(
         &path,
         "\
-mod context;
-mod ids;
+#[cfg(test)]
+mod tests {
+    use super::*;
+}
 
 use std::fs;
-use std::path::Path;
 
 pub fn run() {}
 ",
@@ -289,32 +447,3638 @@ pub fn run() {}
 
     let result = run_reorder(&path);
 
-    let use_pos = result.find("use std::fs").expect("use statement not found");
-    let mod_pos = result.find("mod context").expect("mod not found");
+    let use_pos = result.find("use std::fs").expect("use not found");
     let fn_pos = result.find("pub fn run").expect("fn not found");
+    let test_pos = result.find("#[cfg(test)]").expect("#[cfg(test)] not found");
     assert!(
-        use_pos < mod_pos,
-        "use statements should come before mod: got use at {use_pos}, mod at {mod_pos}"
+        test_pos > use_pos,
+        "#[cfg(test)] mod should be after use at {use_pos}, got test at {test_pos}"
     );
     assert!(
-        mod_pos < fn_pos,
+        test_pos > fn_pos,
+        "#[cfg(test)] mod should be after fn at {fn_pos}, got test at {test_pos}"
+    );
+}
+
+#[test]
+fn test_check_mode_leaves_file_untouched() {
+    let path = test_dir().join("check_unsorted.rs");
+    let src = "\
+pub fn run() {}
+
+use std::fs;
+";
+    fs::write(&path, src).expect("failed to write test file");
+
+    let output = run_refmt(&["--check", path.to_str().unwrap()]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "unsorted file should fail check"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("check_unsorted.rs"),
+        "stderr should name the unsorted file: {stderr}"
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(result, src, "check mode must not rewrite the file");
+}
+
+#[test]
+fn test_check_mode_passes_sorted_file() {
+    let path = test_dir().join("check_sorted.rs");
+    fs::write(
+        &path,
+        "\
+use std::fs;
+
+pub fn run() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--check", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "sorted file should pass check: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_check_with_diff_prints_sorted_diffs() {
+    let dir = tmp_dir("check_diff");
+    let src = "pub fn run() {}\n\nuse std::fs;\n";
+    for name in ["b.rs", "a.rs", "c.rs"] {
+        fs::write(dir.join(name), src).expect("failed to write test file");
+    }
+    fs::write(dir.join("c.rs"), "use std::fs;\n").expect("failed to write test file");
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let (a, b, c) = (path("a.rs"), path("b.rs"), path("c.rs"));
+
+    let output = run_refmt(&["--check", "--diff", "--jobs", "2", &c, &b, &a]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "changes should fail the check"
+    );
+    let hunk = "\
+@@ -1,3 +1,3 @@
+-pub fn run() {}
+-
+ use std::fs;
++
++pub fn run() {}
+";
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("--- {a}\n+++ {a}\n{hunk}--- {b}\n+++ {b}\n{hunk}")
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "2 of 3 file(s) would be reordered\n"
+    );
+    for name in ["a.rs", "b.rs"] {
+        assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), src);
+    }
+}
+
+#[test]
+fn test_collapse_blank_lines() {
+    let input = "\
+fn run() {
+    let first = 1;
+
+
+    let second = 2;
+}
+
+/// Parses the input.
+
+
+#[inline]
+#[must_use]
+
+
+
+// Kept as a fallback.
+fn parse() -> u8 {
+    let value = \"
+
+
+\";
+    0
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--collapse-blank-lines"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+/// Parses the input.
+
+#[inline]
+#[must_use]
+
+// Kept as a fallback.
+fn parse() -> u8 {
+    let value = \"
+
+
+\";
+    0
+}
+
+fn run() {
+    let first = 1;
+
+
+    let second = 2;
+}
+"
+    );
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("/// Parses the input.\n\n\n#[inline]"),
+        "stdout: {stdout}"
+    );
+}
+
+#[test]
+fn test_comment_loss() {
+    let path = test_dir().join("comment_loss.rs");
+    let src = "fn b() {}\n\n// orphaned\n\nfn a() {}\n";
+    fs::write(&path, src).expect("failed to write test file");
+
+    let output = run_refmt(&["--deny-comment-loss", path.to_str().unwrap()]);
+
+    assert!(!output.status.success(), "comment loss should fail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would drop comments"), "got {stderr}");
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(result, src, "the file must not be rewritten");
+
+    let output = run_refmt(&[path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "warning: reordering {} drops comments",
+            path.display()
+        )),
+        "got {stderr}"
+    );
+
+    let output = run_refmt_stdin(&["--stdin", "--deny-comment-loss"], src);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        src,
+        "stdin must come back unchanged"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("reordering stdin would drop comments"),
+        "got {stderr}"
+    );
+}
+
+#[test]
+fn test_comments_between_crate_attributes_stay_in_place() {
+    let input = "\
+#![cfg_attr(feature = \"x\", no_std)]
+// Lints, kept apart from the attribute below.
+
+/* Checked in CI. */
+#![deny(missing_docs)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+fn b() {}
+fn a() {}
+";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+#![cfg_attr(feature = \"x\", no_std)]
+// Lints, kept apart from the attribute below.
+
+/* Checked in CI. */
+#![deny(missing_docs)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+fn a() {}
+
+fn b() {}
+"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+}
+
+#[test]
+fn test_config_blank_lines() {
+    let path = test_dir().join("config_blank_lines.rs");
+    let config = test_dir().join("config_blank_lines.toml");
+    fs::write(&config, "blank_lines_between = 2\nblank_lines_within = 0\n")
+        .expect("failed to write config");
+    fs::write(
+        &path,
+        "\
+fn b() {}
+
+fn a() {}
+
+use std::io;
+use std::fs;
+
+struct Foo;
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+use std::io;
+
+
+struct Foo;
+
+
+fn a() {}
+fn b() {}
+"
+    );
+}
+
+#[test]
+fn test_config_category_order() {
+    let path = test_dir().join("config_order.rs");
+    let config = test_dir().join("config_order.toml");
+    fs::write(&config, "category_order = [\"fn\", \"impl\", \"use\"]\n")
+        .expect("failed to write config");
+    fs::write(
+        &path,
+        "\
+use std::fs;
+
+pub struct Foo;
+
+impl Foo {}
+
+pub fn run() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+pub fn run() {}
+
+impl Foo {}
+
+use std::fs;
+
+pub struct Foo;
+"
+    );
+}
+
+#[test]
+fn test_config_discover_uses_nearest() {
+    let dir = tmp_dir("config_discover");
+    let src = "use std::fs;\n\npub fn run() {}\n";
+    let write = |path: &str, text: &str| {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).expect("failed to create dir");
+        fs::write(path, text).expect("failed to write test file");
+    };
+    write("reorder.toml", "category_order = [\"fn\", \"use\"]\n");
+    write("sub/reorder.toml", "category_order = [\"use\", \"fn\"]\n");
+    for path in [
+        "top.rs",
+        "other/top.rs",
+        "sub/nested.rs",
+        "sub/deep/nested.rs",
+    ] {
+        write(path, src);
+    }
+
+    let output = run_refmt(&["--config-discover", dir.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |path: &str| fs::read_to_string(dir.join(path)).expect("failed to read file");
+    let fn_first = "pub fn run() {}\n\nuse std::fs;\n";
+    assert_eq!(read("top.rs"), fn_first);
+    assert_eq!(read("other/top.rs"), fn_first);
+    assert_eq!(read("sub/nested.rs"), src, "the nearest config wins");
+    assert_eq!(read("sub/deep/nested.rs"), src);
+
+    let output = Command::new(cargo_bin())
+        .args(["--config-discover", "--stdin"])
+        .current_dir(dir.join("sub/deep"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(fn_first.as_bytes())?;
+            child.wait_with_output()
+        })
+        .expect("failed to run refmt");
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), src);
+}
+
+#[test]
+fn test_config_rejects_unknown_category() {
+    let path = test_dir().join("config_invalid.rs");
+    let config = test_dir().join("config_invalid.toml");
+    fs::write(&config, "category_order = [\"fn\", \"widgets\"]\n").expect("failed to write config");
+    fs::write(&path, "pub fn run() {}\n").expect("failed to write test file");
+
+    let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+
+    assert!(!output.status.success(), "invalid config should fail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unknown category `widgets`"),
+        "stderr should name the bad category: {stderr}"
+    );
+}
+
+#[test]
+fn test_constants_no_blank_lines() {
+    let path = test_dir().join("constants.rs");
+    fs::write(
+        &path,
+        "\
+const DEFAULT_MODEL: &str = \"gpt-5.4\";
+
+const EXECUTOR_TURNS: usize = 12;
+
+const IMPLEMENTATION_RETRY_LIMIT: usize = 3;
+
+const MAX_FINAL_REVIEW_PASSES: usize = 3;
+
+const WORKFLOW_MAX_CONCURRENCY: usize = 4;
+
+const WORKTREE_DIR: &str = \".mmat-worktrees\";
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+const DEFAULT_MODEL: &str = \"gpt-5.4\";
+const EXECUTOR_TURNS: usize = 12;
+const IMPLEMENTATION_RETRY_LIMIT: usize = 3;
+const MAX_FINAL_REVIEW_PASSES: usize = 3;
+const WORKFLOW_MAX_CONCURRENCY: usize = 4;
+const WORKTREE_DIR: &str = \".mmat-worktrees\";
+"
+    );
+}
+
+#[test]
+fn test_continue_on_error() {
+    let dir = tmp_dir("continue_on_error");
+    fs::write(dir.join("a_broken.rs"), "fn broken( {").expect("failed to write test file");
+    fs::write(dir.join("b_valid.rs"), "pub fn run() {}\n\nuse std::fs;\n")
+        .expect("failed to write test file");
+
+    let output = run_refmt(&[
+        "--continue-on-error",
+        "--fail-on-parse-error",
+        dir.to_str().unwrap(),
+    ]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "failures should exit non-zero"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("a_broken.rs"), "stderr: {stderr}");
+    assert!(
+        stderr.contains("failed to reorder 1 file(s)"),
+        "stderr: {stderr}"
+    );
+    let valid = fs::read_to_string(dir.join("b_valid.rs")).expect("failed to read file");
+    assert_eq!(valid, "use std::fs;\n\npub fn run() {}\n");
+}
+
+#[test]
+fn test_crate_docs_and_attributes_stay_on_top() {
+    let path = test_dir().join("crate_header.rs");
+    fs::write(
+        &path,
+        "\
+//! Crate docs.
+//!
+//! More docs.
+
+#![deny(missing_docs)] // kept strict
+//! Docs after an attribute.
+#![allow(dead_code)]
+
+/// Runs.
+pub fn run() {}
+
+use std::fs;
+
+mod inner {
+    //! Inner docs.
+
+    // Why inner code is allowed to be dead.
+    #![allow(dead_code)]
+
+    fn b() {}
+
+    fn a() {}
+}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+//! Crate docs.
+//!
+//! More docs.
+
+#![deny(missing_docs)] // kept strict
+//! Docs after an attribute.
+#![allow(dead_code)]
+
+use std::fs;
+
+mod inner {
+    //! Inner docs.
+
+    // Why inner code is allowed to be dead.
+    #![allow(dead_code)]
+
+    fn a() {}
+
+    fn b() {}
+}
+
+/// Runs.
+pub fn run() {}
+"
+    );
+}
+
+#[test]
+fn test_crlf_line_endings_preserved() {
+    let path = test_dir().join("crlf.rs");
+    fs::write(
+        &path,
+        "pub fn run() {\r\n    let x = 1;\r\n}\r\n\r\nuse std::fs;\r\nuse std::io;\r\n",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "use std::fs;\r\nuse std::io;\r\n\r\npub fn run() {\r\n    let x = 1;\r\n}\r\n"
+    );
+}
+
+#[test]
+fn test_depth_limits_directory_walk() {
+    let dir = tmp_dir("depth");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    for path in ["top.rs", "a/one.rs", "a/b/two.rs", "a/b/c/three.rs"] {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).expect("failed to create dir");
+        fs::write(path, unsorted).expect("failed to write test file");
+    }
+
+    let output = run_refmt(&["--list-changed", "--depth", "1", dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}\n{}\n",
+            dir.join("top.rs").display(),
+            dir.join("a/one.rs").display()
+        )
+    );
+
+    let output = run_refmt(&["--list-changed", "--depth", "0", dir.to_str().unwrap()]);
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", dir.join("top.rs").display())
+    );
+}
+
+#[test]
+fn test_diff_color() {
+    let path = test_dir().join("diff_color.rs");
+    let src = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::write(&path, src).expect("failed to write test file");
+
+    let output = run_refmt(&["--diff", "--color=always", path.to_str().unwrap()]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\x1b[31m-pub fn run() {}\x1b[0m\n"),
+        "got {stdout}"
+    );
+    assert!(
+        stdout.contains("\x1b[32m+pub fn run() {}\x1b[0m\n"),
+        "got {stdout}"
+    );
+
+    // Piped output is plain unless color is forced.
+    let output = run_refmt(&["--diff", path.to_str().unwrap()]);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains('\x1b'));
+
+    let output = run_refmt(&["--color=always", path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(result, "use std::fs;\n\npub fn run() {}\n");
+}
+
+#[test]
+fn test_diff_mode_prints_unified_diff() {
+    let path = test_dir().join("diff_unsorted.rs");
+    let src = "\
+pub fn run() {}
+
+use std::fs;
+";
+    fs::write(&path, src).expect("failed to write test file");
+    let display = path.to_str().unwrap();
+
+    let output = run_refmt(&["--diff", display]);
+
+    assert_eq!(output.status.code(), Some(1), "diff output should fail");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "\
+--- {display}
++++ {display}
+@@ -1,3 +1,3 @@
+-pub fn run() {{}}
+-
+ use std::fs;
++
++pub fn run() {{}}
+"
+        )
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(result, src, "diff mode must not rewrite the file");
+}
+
+#[test]
+fn test_diff_mode_silent_for_sorted_file() {
+    let path = test_dir().join("diff_sorted.rs");
+    fs::write(
+        &path,
+        "\
+use std::fs;
+
+pub fn run() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--diff", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "sorted file should produce no diff"
+    );
+    assert!(output.stdout.is_empty(), "sorted file should print nothing");
+}
+
+#[test]
+fn test_diff_stat() {
+    let dir = tmp_dir("diff_stat");
+    let swapped = dir.join("swapped.rs");
+    let src = "fn b() {}\n\nfn a() {}\n";
+    fs::write(&swapped, src).expect("failed to write test file");
+    fs::write(dir.join("sorted.rs"), "fn a() {}\n").expect("failed to write test file");
+
+    let output = run_refmt(&["--diff-stat", dir.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            " {} | 2 moved\n 1 file(s) changed, 2 item(s) moved\n",
+            swapped.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&swapped).unwrap(), src);
+}
+
+#[test]
+fn test_doc_attributes_move_with_their_items() {
+    let input = "\
+#![doc = \"Crate docs.\"]
+#![doc = include_str!(\"../README.md\")]
+
+fn b() {}
+
+#[doc = \"First line.\"]
+#[inline]
+#[doc = \"Second
+ line.\"]
+/// Third.
+#[doc = r#\"Raw \"quoted\".\"#]
+pub fn a() {}
+
+mod inner {
+    #![doc = \"Inner docs.\"]
+
+    fn d() {}
+
+    #[doc = \"C.\"]
+    #[doc = \"Again.\"]
+    fn c() {}
+}
+
+use std::fs;
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--sort-within"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+#![doc = \"Crate docs.\"]
+#![doc = include_str!(\"../README.md\")]
+
+use std::fs;
+
+mod inner {
+    #![doc = \"Inner docs.\"]
+
+    #[doc = \"C.\"]
+    #[doc = \"Again.\"]
+    fn c() {}
+
+    fn d() {}
+}
+
+#[doc = \"First line.\"]
+#[inline]
+#[doc = \"Second
+ line.\"]
+/// Third.
+#[doc = r#\"Raw \"quoted\".\"#]
+pub fn a() {}
+
+fn b() {}
+"
+    );
+}
+
+#[test]
+fn test_doc_comments_travel_with_item() {
+    let path = test_dir().join("doc_comments.rs");
+    fs::write(
+        &path,
+        "\
+/// Runs the thing.
+///
+/// More detail.
+#[inline]
+/// Continued after an attribute.
+pub fn run() {}
+
+/**
+ * Block docs for Config.
+ */
+pub struct Config;
+
+/** Inline block doc. */
+#[derive(Debug)]
+pub enum Mode {
+    A,
+}
+
+use std::fs;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+/**
+ * Block docs for Config.
+ */
+pub struct Config;
+
+/** Inline block doc. */
+#[derive(Debug)]
+pub enum Mode {
+    A,
+}
+
+/// Runs the thing.
+///
+/// More detail.
+#[inline]
+/// Continued after an attribute.
+pub fn run() {}
+"
+    );
+    assert_eq!(result.matches("Runs the thing").count(), 1);
+}
+
+#[test]
+fn test_edition_2015_leads_with_extern_crate() {
+    let path = test_dir().join("edition_2015.rs");
+    let src = "\
+use std::fs;
+mod a;
+#[macro_use]
+extern crate log;
+extern crate serde;
+
+fn run() {}
+";
+    fs::write(&path, src).expect("failed to write test file");
+
+    let output = run_refmt(&["--edition", "2015", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+#[macro_use]
+extern crate log;
+extern crate serde;
+
+use std::fs;
+
+mod a;
+
+fn run() {}
+"
+    );
+
+    // Later editions follow a `category_order` that moves them.
+    let config = test_dir().join("edition_2015.toml");
+    fs::write(
+        &config,
+        "category_order = [\"use\", \"mod\", \"extern_crate\"]\n",
+    )
+    .expect("failed to write config");
+    for (edition, expected) in [
+        ("2015", "#[macro_use]\nextern crate log;"),
+        (
+            "2021",
+            "use std::fs;\n\nmod a;\n\n#[macro_use]\nextern crate log;",
+        ),
+    ] {
+        fs::write(&path, src).expect("failed to write test file");
+        let output = run_refmt(&[
+            "--edition",
+            edition,
+            "--config",
+            config.to_str().unwrap(),
+            path.to_str().unwrap(),
+        ]);
+
+        assert!(output.status.success());
+        let result = fs::read_to_string(&path).expect("failed to read file");
+        assert!(result.starts_with(expected), "{edition}: got {result}");
+    }
+}
+
+#[test]
+fn test_editorconfig_line_endings() {
+    let dir = tmp_dir("editorconfig");
+    fs::write(
+        dir.join(".editorconfig"),
+        "root = true\n\n[*.rs]\nend_of_line = crlf\ninsert_final_newline = true\n",
+    )
+    .unwrap();
+    let path = dir.join("lib.rs");
+    let src = "fn b() {}\nfn a() {}";
+    fs::write(&path, src).unwrap();
+
+    let output = run_refmt(&["--no-editorconfig", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fn a() {}\n\nfn b() {}");
+
+    fs::write(&path, src).unwrap();
+    let output = run_refmt(&[path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "fn a() {}\r\n\r\nfn b() {}\r\n"
+    );
+
+    // A nearer file takes precedence.
+    fs::create_dir(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/.editorconfig"), "[*]\nend_of_line = lf\n").unwrap();
+    let path = dir.join("sub/lib.rs");
+    fs::write(&path, src).unwrap();
+    let output = run_refmt(&[path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "fn a() {}\n\nfn b() {}\n"
+    );
+}
+
+#[test]
+fn test_exclude_skips_matching_paths() {
+    let dir = tmp_dir("exclude");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::create_dir_all(dir.join("bindings")).expect("failed to create test dir");
+    fs::write(dir.join("lib.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("api_generated.rs"), unsorted).expect("failed to write test file");
+    // Unparseable, so the run fails if the file is read at all.
+    fs::write(dir.join("bindings/ffi.rs"), "fn broken( {").expect("failed to write test file");
+
+    let output = run_refmt(&[
+        "--exclude",
+        "*_generated.rs",
+        "--exclude",
+        "bindings/**",
+        dir.to_str().unwrap(),
+    ]);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("lib.rs"), "use std::fs;\n\npub fn run() {}\n");
+    assert_eq!(read("api_generated.rs"), unsorted);
+    assert_eq!(read("bindings/ffi.rs"), "fn broken( {");
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = tmp_dir("exit_codes");
+    let path = dir.join("lib.rs");
+    let path_str = path.to_str().unwrap();
+    let status = |args: &[&str]| run_refmt(args).status.code();
+
+    fs::write(&path, "use std::fs;\n\npub fn run() {}\n").expect("failed to write test file");
+    assert_eq!(status(&["--check", path_str]), Some(0));
+    assert_eq!(status(&[path_str]), Some(0));
+
+    fs::write(&path, "pub fn run() {}\n\nuse std::fs;\n").expect("failed to write test file");
+    assert_eq!(status(&["--check", path_str]), Some(1));
+    assert_eq!(status(&["--diff", path_str]), Some(1));
+    assert_eq!(status(&[path_str]), Some(0), "rewriting is a success");
+
+    fs::write(&path, "fn broken( {\n").expect("failed to write test file");
+    assert_eq!(status(&["--check", path_str]), Some(0));
+    assert_eq!(
+        status(&["--check", "--fail-on-parse-error", path_str]),
+        Some(2)
+    );
+    let missing = dir.join("missing.rs");
+    assert_eq!(status(&[missing.to_str().unwrap()]), Some(2));
+
+    assert_eq!(status(&["--no-such-flag"]), Some(3));
+    assert_eq!(status(&["--range", "1:2", path_str]), Some(3));
+    let config = dir.join("reorder.toml");
+    fs::write(&config, "category_order = [\"nope\"]\n").expect("failed to write config");
+    assert_eq!(
+        status(&["--config", config.to_str().unwrap(), path_str]),
+        Some(3)
+    );
+    assert_eq!(status(&["--help"]), Some(0));
+}
+
+#[test]
+fn test_explain_reports_categories() {
+    let path = test_dir().join("explain.rs");
+    let src = "fn run() {}\npub use a::A;\nuse std::fs;\nmacro_rules! m { () => {} }\nmod declared;\nmod inline {}\nextern crate alloc;\ntype Alias = u8;\nconst C: u8 = 0;\ntrait T {}\nstruct S;\nunion U { a: u8 }\nimpl S {}\nextern \"C\" {}\n#[cfg(test)]\nmod tests {}\n#[test]\nfn check() {}\n#[cfg(test)]\nfn helper() {}\n";
+    fs::write(&path, src).unwrap();
+
+    let output = run_refmt(&["--explain", path.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), src);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let prefix = format!("{}:", path.display());
+    let lines: Vec<_> = stdout
+        .lines()
+        .map(|line| {
+            line.strip_prefix(&prefix)
+                .expect("line starts with the path")
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "1: Fn run -> fn (#14)",
+            "2: Use (unnamed) -> pub_use (#1)",
+            "3: Use (unnamed) -> use (#2)",
+            "4: Macro m -> macro (#3)",
+            "5: Mod declared -> mod (#4)",
+            "6: Mod inline -> inline_mod (#5)",
+            "7: ExternCrate alloc -> extern_crate (#0)",
+            "8: Type Alias -> type (#6)",
+            "9: Const C -> const (#7)",
+            "10: Trait T -> trait (#8)",
+            "11: Struct S -> typedef (#9)",
+            "12: Union U -> union (#10)",
+            "13: Impl (unnamed) -> impl (#11)",
+            "14: ForeignMod (unnamed) -> foreign (#12)",
+            "15: Mod tests -> tests (#15) [cfg(test) module]",
+            "17: Fn check -> tests (#15) [#[test]]",
+            "19: Fn helper -> tests (#15) [cfg(test)]",
+        ]
+    );
+}
+
+#[test]
+fn test_files_from_reads_listed_paths() {
+    let dir = tmp_dir("files_from");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    for name in ["a.rs", "b c.rs", "unlisted.rs"] {
+        fs::write(dir.join(name), unsorted).expect("failed to write test file");
+    }
+    let a = dir.join("a.rs");
+    let b = dir.join("b c.rs");
+    let list = format!(
+        "{}\0{}\0{}\0",
+        a.display(),
+        b.display(),
+        dir.join(".").join("a.rs").display()
+    );
+
+    let output = run_refmt_stdin(&["--files-from", "-", "--null"], &list);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        String::from_utf8_lossy(&output.stderr).ends_with("reordered 2 of 2 file(s)\n"),
+        "duplicate paths should be processed once"
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("a.rs"), sorted);
+    assert_eq!(read("b c.rs"), sorted);
+    assert_eq!(read("unlisted.rs"), unsorted);
+
+    fs::write(&a, unsorted).expect("failed to write test file");
+    let list_file = dir.join("files.txt");
+    fs::write(&list_file, format!("{}\n", a.display())).expect("failed to write file list");
+    let output = run_refmt(&["--check", "--files-from", list_file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(read("a.rs"), unsorted);
+}
+
+#[test]
+fn test_files_without_items_unchanged() {
+    let dir = tmp_dir("without_items");
+    let cases = [
+        ("empty.rs", ""),
+        (
+            "comments.rs",
+            "// Copyright (c) Example\n// SPDX-License-Identifier: MIT\n",
+        ),
+        (
+            "header.rs",
+            "//! Crate docs.\n\n// Items are added later.\n",
+        ),
+    ];
+    for (name, src) in cases {
+        fs::write(dir.join(name), src).expect("failed to write test file");
+    }
+
+    let output = run_refmt(&[dir.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "reordered 0 of 3 file(s)\n"
+    );
+    for (name, src) in cases {
+        let result = fs::read_to_string(dir.join(name)).expect("failed to read file");
+        assert_eq!(result, src, "{name} must be left byte-identical");
+    }
+}
+
+#[test]
+fn test_fn_visibility_order() {
+    let path = test_dir().join("fn_visibility.rs");
+    fs::write(
+        &path,
+        "\
+fn private_fn() {}
+
+pub(crate) fn crate_fn() {}
+
+pub fn public_fn() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    let pub_pos = result.find("pub fn public_fn").expect("pub fn not found");
+    let crate_pos = result
+        .find("pub(crate) fn crate_fn")
+        .expect("pub(crate) fn not found");
+    let priv_pos = result.find("fn private_fn").expect("private fn not found");
+    assert!(
+        pub_pos < crate_pos,
+        "pub fn should come before pub(crate) fn: got pub at {pub_pos}, pub(crate) at {crate_pos}"
+    );
+    assert!(
+        crate_pos < priv_pos,
+        "pub(crate) fn should come before private fn: got pub(crate) at {crate_pos}, private at {priv_pos}"
+    );
+}
+
+#[test]
+fn test_follow_mods() {
+    let dir = tmp_dir("follow_mods");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    let write = |path: &str, src: &str| {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).expect("failed to create dir");
+        fs::write(path, src).expect("failed to write test file");
+    };
+    write(
+        "lib.rs",
+        "mod flat;\nmod nested;\n#[path = \"elsewhere/renamed.rs\"]\nmod custom;\nmod inline {\n    mod inner;\n}\n",
+    );
+    write("flat.rs", &format!("mod child;\n\n{unsorted}"));
+    write("flat/child.rs", unsorted);
+    write("nested/mod.rs", unsorted);
+    write("elsewhere/renamed.rs", unsorted);
+    write("inline/inner.rs", unsorted);
+    write("unrelated.rs", unsorted);
+
+    let output = run_refmt(&["--follow-mods", dir.join("lib.rs").to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |path: &str| fs::read_to_string(dir.join(path)).expect("failed to read file");
+    assert_eq!(
+        read("flat.rs"),
+        "use std::fs;\n\nmod child;\n\npub fn run() {}\n"
+    );
+    for path in [
+        "flat/child.rs",
+        "nested/mod.rs",
+        "elsewhere/renamed.rs",
+        "inline/inner.rs",
+    ] {
+        assert_eq!(read(path), sorted, "{path} should be reordered");
+    }
+    assert_eq!(read("unrelated.rs"), unsorted);
+}
+
+#[test]
+fn test_follow_mods_path_attributes() {
+    let dir = tmp_dir("follow_mods_path");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    let write = |path: &str, src: &str| {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).expect("failed to create dir");
+        fs::write(path, src).expect("failed to write test file");
+    };
+    write(
+        "src/lib.rs",
+        "#[path = \"../weird/location.rs\"]\nmod foo;\n",
+    );
+    // A `#[path]` file owns its directory, so `mod bar;` is `weird/bar.rs`.
+    write(
+        "weird/location.rs",
+        &format!("mod bar;\n#[path = \"deeper/named.rs\"]\nmod baz;\n\n{unsorted}"),
+    );
+    write("weird/bar.rs", unsorted);
+    write("weird/deeper/named.rs", unsorted);
+    write("weird/location/bar.rs", unsorted);
+    write("src/foo.rs", unsorted);
+
+    let output = run_refmt(&["--follow-mods", dir.join("src/lib.rs").to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |path: &str| fs::read_to_string(dir.join(path)).expect("failed to read file");
+    assert!(read("weird/location.rs").starts_with("use std::fs;\n"));
+    assert_eq!(read("weird/bar.rs"), sorted);
+    assert_eq!(read("weird/deeper/named.rs"), sorted);
+    assert_eq!(read("weird/location/bar.rs"), unsorted);
+    assert_eq!(read("src/foo.rs"), unsorted);
+}
+
+#[test]
+fn test_force_partial_reorders_parseable_prefix() {
+    let dir = tmp_dir("force_partial");
+    let partial = "pub fn run() {}\n\nuse std::fs;\n\nfn broken( {\n    let x = 1;\n";
+    let broken = "fn broken( {\n";
+    fs::write(dir.join("partial.rs"), partial).expect("failed to write test file");
+    fs::write(dir.join("broken.rs"), broken).expect("failed to write test file");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--check", "--fail-on-parse-error", dir_str]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "parse errors fail with --fail-on-parse-error"
+    );
+
+    let output = run_refmt(&["--force-partial", dir_str]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: skipping") && stderr.contains("broken.rs"),
+        "stderr: {stderr}"
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(
+        read("partial.rs"),
+        "use std::fs;\n\npub fn run() {}\n\nfn broken( {\n    let x = 1;\n"
+    );
+    assert_eq!(read("broken.rs"), broken);
+
+    let output = run_refmt(&["--check", "--force-partial", dir_str]);
+
+    assert_eq!(output.status.code(), Some(0), "partial output is stable");
+}
+
+#[test]
+fn test_foreign_blocks_have_own_section() {
+    let input = "\
+fn helper() {}
+
+unsafe extern \"system\" {
+    pub safe fn tick();
+}
+
+pub fn run() {}
+
+extern \"C\" {
+    fn abs(x: i32) -> i32;
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+unsafe extern \"system\" {
+    pub safe fn tick();
+}
+
+extern \"C\" {
+    fn abs(x: i32) -> i32;
+}
+
+pub fn run() {}
+
+fn helper() {}
+"
+    );
+
+    let config = tmp_dir("foreign_order").join("reorder.toml");
+    fs::write(&config, "category_order = [\"fn\", \"foreign\"]\n").expect("failed to write config");
+
+    let output = run_refmt_stdin(&["--stdin", "--config", config.to_str().unwrap()], input);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("pub fn run() {}\n\nfn helper() {}\n\nunsafe extern \"system\" {"),
+        "stdout: {stdout}"
+    );
+}
+
+#[test]
+fn test_generated_files_skipped() {
+    let dir = tmp_dir("generated");
+    let generated = "// @generated by build.rs\npub fn run() {}\n\nuse std::fs;\n";
+    let custom = "// Code generated by tool. DO NOT EDIT.\npub fn run() {}\n\nuse std::fs;\n";
+    fs::write(dir.join("generated.rs"), generated).expect("failed to write test file");
+    fs::write(dir.join("custom.rs"), custom).expect("failed to write test file");
+    fs::write(dir.join("lib.rs"), "pub fn run() {}\n\nuse std::fs;\n")
+        .expect("failed to write test file");
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+
+    let output = run_refmt(&["--verbose", dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("skipped generated file"),
+        "stderr: {stderr}"
+    );
+    assert_eq!(read("generated.rs"), generated);
+    assert_eq!(read("lib.rs"), "use std::fs;\n\npub fn run() {}\n");
+    assert_ne!(read("custom.rs"), custom);
+
+    fs::write(dir.join("custom.rs"), custom).expect("failed to write test file");
+    let config = dir.join("reorder.toml");
+    fs::write(&config, "generated_marker = \"DO NOT EDIT\"\n").expect("failed to write config");
+
+    let output = run_refmt(&["--config", config.to_str().unwrap(), dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(read("custom.rs"), custom);
+    assert_ne!(read("generated.rs"), generated);
+}
+
+#[test]
+fn test_gitignore_respected_when_walking() {
+    let dir = tmp_dir("gitignore");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    fs::create_dir_all(dir.join("target/debug")).expect("failed to create test dir");
+    fs::write(
+        dir.join(".gitignore"),
+        "target/\n*_generated.rs\n!keep_generated.rs\n",
+    )
+    .expect("failed to write test file");
+    for name in [
+        "lib.rs",
+        "api_generated.rs",
+        "keep_generated.rs",
+        "target/debug/build.rs",
+    ] {
+        fs::write(dir.join(name), unsorted).expect("failed to write test file");
+    }
+
+    let output = run_refmt(&[dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("lib.rs"), sorted);
+    assert_eq!(read("keep_generated.rs"), sorted);
+    assert_eq!(read("api_generated.rs"), unsorted);
+    assert_eq!(read("target/debug/build.rs"), unsorted);
+
+    let explicit = dir.join("api_generated.rs");
+    let output = run_refmt(&[explicit.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(read("api_generated.rs"), sorted);
+
+    let output = run_refmt(&["--no-gitignore", dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(read("target/debug/build.rs"), sorted);
+}
+
+#[test]
+fn test_glob_arguments() {
+    let dir = tmp_dir("glob_args");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::create_dir_all(dir.join("a/c")).expect("failed to create test dir");
+    fs::create_dir_all(dir.join(".hidden")).expect("failed to create test dir");
+    for name in [
+        "lib.rs",
+        "a/b.rs",
+        "a/c/d.rs",
+        ".hidden/e.rs",
+        "a/notes.txt",
+    ] {
+        fs::write(dir.join(name), unsorted).expect("failed to write test file");
+    }
+    let listed = |pattern: &str| {
+        let output = run_refmt(&["--list-changed", dir.join(pattern).to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut names: Vec<String> = stdout
+            .lines()
+            .map(|line| {
+                let path = Path::new(line)
+                    .strip_prefix(&dir)
+                    .expect("path outside test dir");
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(listed("**/*.rs"), ["a/b.rs", "a/c/d.rs", "lib.rs"]);
+    assert_eq!(listed("*.rs"), ["lib.rs"]);
+    assert_eq!(listed("a/*"), ["a/b.rs", "a/c/d.rs"]);
+    assert_eq!(listed(".*/*.rs"), [".hidden/e.rs"]);
+
+    let output = run_refmt(&[dir.join("missing/*.rs").to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no files match"), "stderr: {stderr}");
+}
+
+#[test]
+fn test_hidden_directories_skipped() {
+    let dir = tmp_dir("hidden_dirs");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::create_dir_all(dir.join(".hidden")).expect("failed to create test dir");
+    fs::write(dir.join("lib.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join(".hidden/snippet.rs"), unsorted).expect("failed to write test file");
+    let dir_str = dir.to_str().unwrap();
+    let listed = |args: &[&str]| {
+        let output = run_refmt(args);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = listed(&["--list-changed", dir_str]);
+
+    assert!(stdout.contains("lib.rs"), "stdout: {stdout}");
+    assert!(!stdout.contains("snippet.rs"), "stdout: {stdout}");
+
+    let stdout = listed(&["--list-changed", "--include-hidden", dir_str]);
+
+    assert!(stdout.contains("snippet.rs"), "stdout: {stdout}");
+
+    let hidden = dir.join(".hidden");
+    let stdout = listed(&["--list-changed", hidden.to_str().unwrap()]);
+
+    assert!(
+        stdout.contains("snippet.rs"),
+        "explicit hidden dirs are scanned"
+    );
+}
+
+#[test]
+fn test_impl_order_by_type_order() {
+    let path = test_dir().join("impl_order.rs");
+    fs::write(
+        &path,
+        "\
+pub struct ArtifactId(pub String);
+
+pub struct TransitionId(pub String);
+
+pub struct ArtifactRef {
+    data: i32,
+}
+
+impl ArtifactId {
+    pub fn new() -> Self {
+        Self(String::new())
+    }
+}
+
+impl ArtifactRef {
+    pub fn downcast_ref(&self) -> i32 {
+        self.data
+    }
+}
+
+impl Default for ArtifactId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransitionId {
+    pub fn new(id: String) -> Self {
+        Self(id)
+    }
+}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+pub struct ArtifactId(pub String);
+
+pub struct TransitionId(pub String);
+
+pub struct ArtifactRef {
+    data: i32,
+}
+
+impl ArtifactId {
+    pub fn new() -> Self {
+        Self(String::new())
+    }
+}
+
+impl Default for ArtifactId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransitionId {
+    pub fn new(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl ArtifactRef {
+    pub fn downcast_ref(&self) -> i32 {
+        self.data
+    }
+}
+"
+    );
+}
+
+#[test]
+fn test_impls_with_types() {
+    let input = "\
+pub struct Wrapper<T>(T);
+
+pub enum Kind {
+    A,
+}
+
+impl Kind {
+    pub fn is_a(&self) -> bool {
+        true
+    }
+}
+
+impl Display for External {}
+
+impl<T> Wrapper<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Clone> Clone for Wrapper<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl Default for Kind {
+    fn default() -> Self {
+        Kind::A
+    }
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--impls-with-types"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+pub struct Wrapper<T>(T);
+
+impl<T> Wrapper<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Clone> Clone for Wrapper<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+pub enum Kind {
+    A,
+}
+
+impl Kind {
+    pub fn is_a(&self) -> bool {
+        true
+    }
+}
+
+impl Default for Kind {
+    fn default() -> Self {
+        Kind::A
+    }
+}
+
+impl Display for External {}
+"
+    );
+}
+
+#[test]
+fn test_impls_with_types_keep_cfg_gated_impls_in_order() {
+    let input = "\
+fn run() {}
+
+#[cfg(windows)]
+impl Handle {
+    pub fn raw(&self) -> u64 { 0 }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Self { Handle }
+}
+
+#[cfg(unix)]
+impl Handle {
+    pub fn fd(&self) -> i32 { 0 }
+}
+
+pub struct Handle;
+
+impl Alpha {}
+
+struct Alpha;
+";
+
+    for args in [
+        &["--stdin", "--impls-with-types"][..],
+        &[
+            "--stdin",
+            "--impls-with-types",
+            "--sort-within",
+            "--pub-first",
+        ],
+    ] {
+        let output = run_refmt_stdin(args, input);
+
+        assert!(
+            output.status.success(),
+            "reorder failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let handle = stdout.find("pub struct Handle;").unwrap();
+        let windows = stdout.find("#[cfg(windows)]").unwrap();
+        let clone = stdout.find("impl Clone for Handle").unwrap();
+        let unix = stdout.find("#[cfg(unix)]").unwrap();
+        assert!(
+            handle < windows && windows < clone && clone < unix,
+            "impls of Handle should follow it in source order with {args:?}:\n{stdout}"
+        );
+        assert!(
+            stdout.contains("pub struct Handle;\n\n#[cfg(windows)]\nimpl Handle {"),
+            "got:\n{stdout}"
+        );
+    }
+}
+
+#[test]
+fn test_impls_with_types_names_unmatched_impls() {
+    let path = test_dir().join("unmatched_impls.rs");
+    fs::write(
+        &path,
+        "impl Display for Widget {}\n\nimpl Parser for syn::File {}\n\nstruct Widget;\n\nimpl Widgt {}\n",
+    )
+    .unwrap();
+    let display = path.to_str().unwrap();
+
+    let output = run_refmt(&["--impls-with-types", "--check", display]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("matches no local"));
+
+    let output = run_refmt(&["--impls-with-types", "--check", "--verbose", display]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{display}:3: impl for `File` matches no local struct, enum or union\n"
+        )),
+        "got {stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "{display}:7: impl for `Widgt` matches no local struct, enum or union\n"
+        )),
+        "got {stderr}"
+    );
+    assert!(!stderr.contains("`Widget`"), "got {stderr}");
+}
+
+#[test]
+fn test_import_groups_by_origin() {
+    let path = test_dir().join("import_groups.rs");
+    fs::write(
+        &path,
+        "\
+use super::parent;
+use anyhow::Result;
+use core::fmt;
+extern crate serde;
+use self::local::Thing;
+use alloc::vec::Vec;
+use crate::config::Config;
+use std::fs;
+use serde::Deserialize;
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&[path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+extern crate serde;
+
+use alloc::vec::Vec;
+use core::fmt;
+use std::fs;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::config::Config;
+use self::local::Thing;
+use super::parent;
+"
+    );
+}
+
+#[test]
+fn test_import_ordering() {
+    let path = test_dir().join("imports.rs");
+    fs::write(
+        &path,
+        "\
+use uuid::Uuid;
+use std::fs::File;
+use crate::module::Blah;
+use serde::Deserialize;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs::File;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::module::Blah;
+"
+    );
+}
+
+#[test]
+fn test_inner_attributes_not_taken_for_shebang() {
+    let cases = [
+        (
+            "#![allow(unused)]\npub fn run() {}\n\nuse std::fs;\n",
+            "#![allow(unused)]\n\nuse std::fs;\n\npub fn run() {}\n",
+        ),
+        (
+            "#! [allow(unused)]\npub fn run() {}\n\nuse std::fs;\n",
+            "#! [allow(unused)]\n\nuse std::fs;\n\npub fn run() {}\n",
+        ),
+        (
+            "#!\n[allow(unused)]\npub fn run() {}\n\nuse std::fs;\n",
+            "#!\n[allow(unused)]\n\nuse std::fs;\n\npub fn run() {}\n",
+        ),
+        (
+            "//! Docs.\n#![allow(unused)]\npub fn run() {}\n\nuse std::fs;\n",
+            "//! Docs.\n#![allow(unused)]\n\nuse std::fs;\n\npub fn run() {}\n",
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let output = run_refmt_stdin(&["--stdin"], input);
+
+        assert!(
+            output.status.success(),
+            "reorder failed for {input:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+}
+
+#[test]
+fn test_jobs_processes_files_in_parallel() {
+    let dir = tmp_dir("jobs");
+    for name in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        fs::write(dir.join(name), "pub fn run() {}\n\nuse std::fs;\n")
+            .expect("failed to write test file");
+    }
+
+    let output = run_refmt(&["--diff", "--jobs", "3", dir.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let headers: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("--- "))
+        .collect();
+    let expected: Vec<String> = ["a.rs", "b.rs", "c.rs", "d.rs"]
+        .iter()
+        .map(|name| format!("--- {}", dir.join(name).display()))
+        .collect();
+    assert_eq!(headers, expected, "diffs should follow input order");
+
+    let output = run_refmt(&["--jobs", "2", dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    for name in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        let result = fs::read_to_string(dir.join(name)).expect("failed to read file");
+        assert_eq!(result, "use std::fs;\n\npub fn run() {}\n");
+    }
+}
+
+#[test]
+fn test_jobs_rejects_zero() {
+    let output = run_refmt(&["--jobs", "0", "."]);
+
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_leading_comments_travel_with_item() {
+    let path = test_dir().join("leading_comments.rs");
+    fs::write(
+        &path,
+        "\
+// SAFETY: invariants below
+// hold for every caller.
+pub fn run() {}
+
+// Shared buffer size.
+const SIZE: usize = 4;
+
+use std::fs;
+
+// Unrelated note.
+
+// Holds the state.
+pub struct State;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+// Shared buffer size.
+const SIZE: usize = 4;
+
+// Holds the state.
+pub struct State;
+
+// SAFETY: invariants below
+// hold for every caller.
+pub fn run() {}
+"
+    );
+}
+
+#[test]
+fn test_list_changed_prints_paths_only() {
+    let dir = tmp_dir("list_changed");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::write(dir.join("a.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("b.rs"), "use std::fs;\n\npub fn run() {}\n")
+        .expect("failed to write test file");
+    fs::write(dir.join("c.rs"), unsorted).expect("failed to write test file");
+
+    let output = run_refmt(&["--list-changed", dir.to_str().unwrap()]);
+
+    assert!(output.status.success(), "listing should exit 0");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}\n{}\n",
+            dir.join("a.rs").display(),
+            dir.join("c.rs").display()
+        )
+    );
+    let result = fs::read_to_string(dir.join("a.rs")).expect("failed to read file");
+    assert_eq!(result, unsorted, "listing must not rewrite files");
+}
+
+#[test]
+fn test_macro_rules_before_functions() {
+    let path = test_dir().join("macro_rules.rs");
+    fs::write(
+        &path,
+        "\
+pub fn run() -> u32 {
+    square!(3)
+}
+
+lazy_static! {
+    static ref NAME: String = String::new();
+}
+
+macro_rules! square {
+    ($x:expr) => {
+        $x * $x
+    };
+}
+
+use std::fs;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+macro_rules! square {
+    ($x:expr) => {
+        $x * $x
+    };
+}
+
+lazy_static! {
+    static ref NAME: String = String::new();
+}
+
+pub fn run() -> u32 {
+    square!(3)
+}
+"
+    );
+}
+
+#[test]
+fn test_max_file_size_skips_large_files() {
+    let dir = tmp_dir("max_file_size");
+    let small = dir.join("small.rs");
+    let large = dir.join("large.rs");
+    let src = "fn b() {}\n\nfn a() {}\n";
+    let large_src = format!("{src}// {}\n", "x".repeat(100));
+    fs::write(&small, src).unwrap();
+    fs::write(&large, &large_src).unwrap();
+
+    let output = run_refmt(&["--max-file-size", "64", "--verbose", dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "skipped {} of {} bytes\n",
+            large.display(),
+            large_src.len()
+        )),
+        "got {stderr}"
+    );
+    assert!(stderr.contains("reordered 1 of 1 file(s)"), "got {stderr}");
+    assert_eq!(
+        fs::read_to_string(&small).unwrap(),
+        "fn a() {}\n\nfn b() {}\n"
+    );
+    assert_eq!(fs::read_to_string(&large).unwrap(), large_src);
+
+    // A file given on its own is skipped without an error.
+    let output = run_refmt(&["--max-file-size", "64", large.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&large).unwrap(), large_src);
+}
+
+#[test]
+fn test_missing_final_newline_kept() {
+    let dir = tmp_dir("final_newline");
+    let sorted = "use std::fs;\n\npub fn run() {}";
+    let crlf = "pub fn run() {}\r\n\r\nuse std::fs;";
+    fs::write(dir.join("sorted.rs"), sorted).expect("failed to write test file");
+    fs::write(dir.join("crlf.rs"), crlf).expect("failed to write test file");
+    fs::write(dir.join("unsorted.rs"), "pub fn run() {}\n\nuse std::fs;")
+        .expect("failed to write test file");
+
+    let output = run_refmt(&["--check", dir.join("sorted.rs").to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(0), "no newline is not a change");
+
+    let output = run_refmt(&[dir.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("sorted.rs"), sorted);
+    assert_eq!(read("unsorted.rs"), sorted);
+    assert_eq!(read("crlf.rs"), "use std::fs;\r\n\r\npub fn run() {}");
+}
+
+#[test]
+fn test_mod_after_use_not_at_bottom() {
+    let path = test_dir().join("mod_after_use.rs");
+    fs::write(
+        &path,
+        "\
+mod context;
+mod ids;
+
+use std::fs;
+use std::path::Path;
+
+pub fn run() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    let use_pos = result.find("use std::fs").expect("use statement not found");
+    let mod_pos = result.find("mod context").expect("mod not found");
+    let fn_pos = result.find("pub fn run").expect("fn not found");
+    assert!(
+        use_pos < mod_pos,
+        "use statements should come before mod: got use at {use_pos}, mod at {mod_pos}"
+    );
+    assert!(
+        mod_pos < fn_pos,
         "mod should come before fn: got mod at {mod_pos}, fn at {fn_pos}"
     );
 }
 
 #[test]
-fn test_modules_no_blank_lines_between() {
-    let path = test_dir().join("modules.rs");
+fn test_mod_declarations_apart_from_inline_modules() {
+    let path = test_dir().join("mod_declarations.rs");
+    fs::write(
+        &path,
+        "\
+pub mod c;
+mod b {
+    pub fn run() {}
+}
+mod d {}
+mod a;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+mod a;
+pub mod c;
+
+mod b {
+    pub fn run() {}
+}
+
+mod d {}
+"
+    );
+}
+
+#[test]
+fn test_modules_no_blank_lines_between() {
+    let path = test_dir().join("modules.rs");
+    fs::write(
+        &path,
+        "\
+pub mod context;
+
+pub mod ids;
+
+pub mod journal;
+
+pub mod run;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+pub mod context;
+pub mod ids;
+pub mod journal;
+pub mod run;
+"
+    );
+}
+
+#[test]
+fn test_nested_inline_modules_reordered() {
+    let path = test_dir().join("nested_modules.rs");
+    fs::write(
+        &path,
+        "\
+/// Outer docs.
+pub mod outer {
+    #![allow(dead_code)]
+
+    pub fn run() {}
+
+    // Inner module.
+    mod inner {
+        fn helper() {}
+
+        const LIMIT: u8 = 1;
+
+        use std::fs;
+    }
+
+    use std::io;
+}
+
+#[cfg(test)]
+mod tests {
+    fn b() {}
+
+    use super::*;
+}
+
+use std::fmt;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fmt;
+
+/// Outer docs.
+pub mod outer {
+    #![allow(dead_code)]
+
+    use std::io;
+
+    // Inner module.
+    mod inner {
+        use std::fs;
+
+        const LIMIT: u8 = 1;
+
+        fn helper() {}
+    }
+
+    pub fn run() {}
+}
+
+#[cfg(test)]
+mod tests {
+    fn b() {}
+
+    use super::*;
+}
+"
+    );
+}
+
+#[test]
+fn test_no_extra_blank_line_after_last_item() {
+    let path = test_dir().join("last_item.rs");
+    fs::write(
+        &path,
+        "\
+use uuid::Uuid;
+
+pub type RunId = Uuid;
+
+pub struct Foo {
+    bar: i32,
+}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert!(
+        !result.ends_with("\n\n\n"),
+        "should not have extra blank line after last item"
+    );
+}
+
+#[test]
+fn test_no_progress_without_terminal() {
+    let dir = tmp_dir("progress");
+    for index in 0..5 {
+        fs::write(
+            dir.join(format!("f{index}.rs")),
+            "pub fn run() {}\n\nuse std::fs;\n",
+        )
+        .expect("failed to write test file");
+    }
+
+    let output = run_refmt(&[dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !stderr.contains("processed") && !stderr.contains('\r'),
+        "progress shown on a pipe: {stderr:?}"
+    );
+    assert!(
+        stderr.ends_with("reordered 5 of 5 file(s)\n"),
+        "got {stderr:?}"
+    );
+}
+
+#[test]
+fn test_no_reorder_within_group_only_normalizes_spacing() {
+    let input = "\
+fn b() {}
+use std::fs;
+
+
+
+use std::io;
+const A: u8 = 1;
+
+const B: u8 = 2;
+fn a() {}
+// Last.
+fn c() {}
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--no-reorder-within-group"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "\
+fn b() {}
+
+use std::fs;
+use std::io;
+
+const A: u8 = 1;
+const B: u8 = 2;
+
+fn a() {}
+
+// Last.
+fn c() {}
+"
+    );
+    let items = |text: &str| -> Vec<String> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(items(&stdout), items(input), "no item should move");
+}
+
+#[test]
+fn test_non_ascii_source() {
+    let path = test_dir().join("non_ascii.rs");
+    fs::write(
+        &path,
+        "\
+/// Renvoie la valeur par défaut 🎉.
+pub fn défaut() -> u8 { 0 }
+
+/// 配置结构体
+pub struct Größe { wert: u8 } // 🦀
+
+use std::fs;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+/// 配置结构体
+pub struct Größe { wert: u8 } // 🦀
+
+/// Renvoie la valeur par défaut 🎉.
+pub fn défaut() -> u8 { 0 }
+"
+    );
+}
+
+#[test]
+fn test_only_reorders_given_categories() {
+    let input = "fn zeta() {}\n\nuse std::io;\nuse std::fmt;\n\nfn alpha() {}\n\nconst B: u8 = 0;\nconst A: u8 = 0;\n";
+
+    let output = run_refmt_stdin(&["--stdin", "--only", "use"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "fn zeta() {}\n\nuse std::fmt;\nuse std::io;\n\nfn alpha() {}\n\nconst B: u8 = 0;\nconst A: u8 = 0;\n"
+    );
+
+    let output = run_refmt_stdin(&["--stdin", "--only", "use", "--only", "const"], input);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "fn zeta() {}\n\nuse std::fmt;\nuse std::io;\n\nfn alpha() {}\n\nconst A: u8 = 0;\nconst B: u8 = 0;\n"
+    );
+
+    let output = run_refmt_stdin(&["--stdin", "--only", "imports"], input);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_parallel_warnings_print_whole_in_input_order() {
+    let dir = tmp_dir("parallel_warnings");
+    let paths: Vec<_> = (0..8)
+        .map(|i| {
+            let path = dir.join(format!("broken_{i}.rs"));
+            fs::write(&path, "fn broken( {\n").unwrap();
+            path
+        })
+        .collect();
+    let args: Vec<_> = ["--jobs", "4"]
+        .into_iter()
+        .chain(paths.iter().map(|path| path.to_str().unwrap()))
+        .collect();
+
+    let output = run_refmt(&args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let warned: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with("warning: "))
+        .collect();
+    assert_eq!(warned.len(), paths.len(), "got {stderr}");
+    for (line, path) in warned.iter().zip(&paths) {
+        assert!(
+            line.starts_with(&format!("warning: skipping {}: ", path.display())),
+            "got {stderr}"
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_permissions_kept_and_unchanged_files_untouched() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tmp_dir("permissions");
+    let script = dir.join("script.rs");
+    let sorted = dir.join("sorted.rs");
+    fs::write(
+        &script,
+        "#!/usr/bin/env run-cargo-script\nfn main() {}\n\nuse std::fs;\n",
+    )
+    .expect("failed to write test file");
+    fs::write(&sorted, "use std::fs;\n\nfn main() {}\n").expect("failed to write test file");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))
+        .expect("failed to set permissions");
+    let mtime = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .expect("failed to read mtime")
+    };
+    let sorted_mtime = mtime(&sorted);
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    let output = run_refmt(&[script.to_str().unwrap(), sorted.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&script).expect("failed to read file");
+    assert_eq!(
+        result,
+        "#!/usr/bin/env run-cargo-script\n\nuse std::fs;\n\nfn main() {}\n"
+    );
+    let mode = fs::metadata(&script)
+        .expect("failed to inspect file")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(mtime(&sorted), sorted_mtime, "unchanged file was rewritten");
+}
+
+#[test]
+fn test_preserve_cfg_groups() {
+    let path = test_dir().join("preserve_cfg_groups.rs");
+    fs::write(
+        &path,
+        "\
+use std::fs;
+
+#[cfg(feature = \"b\")]
+fn zeta() {}
+#[cfg(feature = \"b\")]
+const LIMIT: u8 = 2;
+
+struct Shared;
+
+#[cfg(feature = \"a\")]
+fn zeta() {}
+#[cfg(feature = \"a\")]
+const LIMIT: u8 = 1;
+
+fn alpha() {}
+
+const SHARED: u8 = 0;
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--preserve-cfg-groups", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+const SHARED: u8 = 0;
+
+struct Shared;
+
+fn alpha() {}
+
+#[cfg(feature = \"b\")]
+fn zeta() {}
+#[cfg(feature = \"b\")]
+const LIMIT: u8 = 2;
+
+#[cfg(feature = \"a\")]
+fn zeta() {}
+#[cfg(feature = \"a\")]
+const LIMIT: u8 = 1;
+"
+    );
+}
+
+#[test]
+fn test_preserve_no_trailing_newline() {
+    let path = test_dir().join("no_newline.rs");
+    fs::write(
+        &path,
+        "\
+use uuid::Uuid;
+
+pub type RunId = Uuid;",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert!(
+        !result.ends_with('\n'),
+        "should not add trailing newline to file without one"
+    );
+}
+
+#[test]
+fn test_preserve_regions() {
+    let input = "fn zeta() {}\n\n// region Helpers\n\nfn helper_b() {}\n\nconst HELPER: u8 = 1;\n\nfn helper_a() {}\n// endregion\nfn loose() {}\n\n// region Queries\nstruct Query;\n\nfn query() {}\n// endregion\n\nconst A: u8 = 2;\n";
+    let output = run_refmt_stdin(&["--preserve-regions", "-"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "const A: u8 = 2;\n\n// region Queries\nstruct Query;\n\nfn query() {}\n// endregion\n\n// region Helpers\n\nfn helper_b() {}\n\nconst HELPER: u8 = 1;\n\nfn helper_a() {}\n// endregion\n\nfn loose() {}\n\nfn zeta() {}\n"
+    );
+
+    let output = run_refmt_stdin(&["-"], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("const A: u8 = 2;\nconst HELPER: u8 = 1;\n"));
+}
+
+#[test]
+fn test_preserve_trailing_newline() {
+    let path = test_dir().join("with_newline.rs");
+    fs::write(
+        &path,
+        "\
+use uuid::Uuid;
+
+pub type RunId = Uuid;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert!(result.ends_with('\n'), "should preserve trailing newline");
+    assert!(
+        !result.ends_with("\n\n"),
+        "should not add extra trailing newline"
+    );
+}
+
+#[test]
+fn test_preserve_use_groups() {
+    let input = "\
+fn run() {}
+
+use std::io;
+use crate::config::Config;
+use std::fs;
+
+use anyhow::Result;
+use crate::diff;
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--preserve-use-groups"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+use crate::config::Config;
+use std::fs;
+use std::io;
+
+use anyhow::Result;
+use crate::diff;
+
+fn run() {}
+"
+    );
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+use std::fs;
+use std::io;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::diff;
+
+fn run() {}
+"
+    );
+}
+
+#[test]
+fn test_proc_macros_lead_the_functions() {
+    let input = "\
+use proc_macro::TokenStream;
+
+pub fn helper() {}
+
+fn expand(input: TokenStream) -> TokenStream {
+    input
+}
+
+#[proc_macro_attribute]
+pub fn traced(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
+/// Derives `Named`.
+#[proc_macro_derive(Named, attributes(name))]
+pub fn derive_named(input: TokenStream) -> TokenStream {
+    expand(input)
+}
+
+#[proc_macro]
+pub fn make(input: TokenStream) -> TokenStream {
+    input
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+use proc_macro::TokenStream;
+
+/// Derives `Named`.
+#[proc_macro_derive(Named, attributes(name))]
+pub fn derive_named(input: TokenStream) -> TokenStream {
+    expand(input)
+}
+
+#[proc_macro]
+pub fn make(input: TokenStream) -> TokenStream {
+    input
+}
+
+#[proc_macro_attribute]
+pub fn traced(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
+pub fn helper() {}
+
+fn expand(input: TokenStream) -> TokenStream {
+    input
+}
+"
+    );
+}
+
+#[test]
+fn test_pub_first_within_categories() {
+    let input = "\
+struct Hidden;
+
+pub struct Shown(Hidden);
+
+pub(crate) struct Shared;
+
+const LIMIT: u8 = 1;
+
+pub const MAX: u8 = 2;
+
+fn helper() {}
+
+pub(crate) fn internal() {}
+
+pub fn api() {}
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--pub-first"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+pub const MAX: u8 = 2;
+const LIMIT: u8 = 1;
+
+pub struct Shown(Hidden);
+
+pub(crate) struct Shared;
+
+struct Hidden;
+
+pub fn api() {}
+
+pub(crate) fn internal() {}
+
+fn helper() {}
+"
+    );
+}
+
+#[test]
+fn test_pub_use_reexports_in_own_section() {
+    let path = test_dir().join("pub_use.rs");
+    fs::write(
+        &path,
+        "\
+use std::fs;
+pub(crate) use crate::util::helper;
+pub use crate::config::Config;
+use anyhow::Result;
+pub use std::io::Error;
+
+pub fn run() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+pub use std::io::Error;
+
+pub use crate::config::Config;
+
+use std::fs;
+
+use anyhow::Result;
+
+pub(crate) use crate::util::helper;
+
+pub fn run() {}
+"
+    );
+}
+
+#[test]
+fn test_quiet_prints_nothing() {
+    let dir = tmp_dir("quiet");
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    fs::write(dir.join("lib.rs"), sorted).expect("failed to write test file");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--quiet", dir_str]);
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty(), "stdout: {:?}", output.stdout);
+    assert!(output.stderr.is_empty(), "stderr: {:?}", output.stderr);
+
+    fs::write(dir.join("lib.rs"), "pub fn run() {}\n\nuse std::fs;\n")
+        .expect("failed to write test file");
+
+    let output = run_refmt(&["-q", "--check", dir_str]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty(), "stdout: {:?}", output.stdout);
+    assert!(output.stderr.is_empty(), "stderr: {:?}", output.stderr);
+}
+
+#[test]
+fn test_range_reorders_only_selected_items() {
+    let input = "\
+fn d() {}
+
+fn c() {}
+
+use std::fs;
+
+fn b() {}
+
+use std::io;
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--range", "3:5"], input);
+
+    assert!(
+        output.status.success(),
+        "range reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+fn d() {}
+
+use std::fs;
+
+fn c() {}
+
+fn b() {}
+
+use std::io;
+"
+    );
+
+    // `fn c` lies inside the range but `fn d` straddles its start.
+    let output = run_refmt_stdin(&["--stdin", "--range", "2:4"], "fn d() {\n}\n\nfn c() {}\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "fn d() {\n}\n\nfn c() {}\n"
+    );
+
+    let output = run_refmt_stdin(&["--stdin", "--range", "5:2"], input);
+    assert!(
+        !output.status.success(),
+        "reversed range should be rejected"
+    );
+}
+
+#[test]
+fn test_raw_identifiers_sort_by_name() {
+    let input = "fn r#type() {}\n\nfn sigma() {}\n\nfn alpha() {}\n\nstatic other: u8 = 0;\n\nstatic r#match: u8 = 0;\n";
+
+    let output = run_refmt_stdin(&["--stdin", "--sort-within"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "static r#match: u8 = 0;\nstatic other: u8 = 0;\n\nfn alpha() {}\n\nfn sigma() {}\n\nfn r#type() {}\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_only_file_is_reported_and_left_alone() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tmp_dir("read_only");
+    let path = dir.join("lib.rs");
+    let unsorted = "fn main() {}\n\nuse std::fs;\n";
+    fs::write(&path, unsorted).expect("failed to write test file");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o444))
+        .expect("failed to set permissions");
+
+    let output = run_refmt(&[path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("cannot write {}: read-only file", path.display())),
+        "got {stderr}"
+    );
+    assert_eq!(
+        fs::read_to_string(&path).expect("failed to read file"),
+        unsorted
+    );
+    assert_eq!(
+        fs::read_dir(&dir)
+            .expect("failed to read directory")
+            .count(),
+        1,
+        "temporary file left behind"
+    );
+}
+
+#[test]
+fn test_reorder_attribute_sets_file_order() {
+    let input =
+        "//! Docs.\n#![reorder(category_order = \"fn, use\")]\n\nuse std::fs;\n\nfn a() {}\n";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "//! Docs.\n#![reorder(category_order = \"fn, use\")]\n\nfn a() {}\n\nuse std::fs;\n"
+    );
+
+    let output = run_refmt_stdin(&["--stdin", "--strip-reorder-attribute"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "//! Docs.\n\nfn a() {}\n\nuse std::fs;\n"
+    );
+
+    // It overrides the configuration file, and may be applied through
+    // `cfg_attr` to keep rustc from rejecting it.
+    let dir = tmp_dir("reorder_attribute");
+    let config = dir.join("reorder.toml");
+    fs::write(&config, "category_order = [\"use\", \"typedef\", \"fn\"]\n").unwrap();
+    let path = dir.join("lib.rs");
+    fs::write(
+        &path,
+        "#![cfg_attr(any(), reorder(category_order = \"fn typedef use\"))]\nuse std::fs;\nstruct S;\nfn a() {}\n",
+    )
+    .unwrap();
+    let output = run_refmt(&["--config", config.to_str().unwrap(), path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "#![cfg_attr(any(), reorder(category_order = \"fn typedef use\"))]\n\nfn a() {}\n\nstruct S;\n\nuse std::fs;\n"
+    );
+
+    let output = run_refmt_stdin(
+        &["--stdin"],
+        "#![reorder(category_order = \"fn, widgets\")]\nuse std::fs;\nfn a() {}\n",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("unknown category `widgets`"),
+        "got {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_reordering_keeps_item_bodies_byte_identical() {
+    let path = test_dir().join("byte_identical_bodies.rs");
+    let long_fn = format!(
+        "pub fn long() -> Vec<&'static str> {{\n    vec![\n{}    ]\n}}",
+        (0..500)
+            .map(|i| format!("        \"line {i}\",\n"))
+            .collect::<String>()
+    );
+    let items = [
+        long_fn.as_str(),
+        "const TEXT: &str = \"  spaced,\n\ttabbed }\n  }\";",
+        "macro_rules! odd {\n    ( $( $x:expr ),* $(,)? ) => {  vec![ $( $x , )* ]  };\n}",
+        "struct Point {\n    x: i32,\n    y: i32,\n}",
+        "fn literal() -> Point {\n    Point {\n        y: 2,\n        x: 1,\n    }\n}",
+        "enum Shape {\n    Circle  { r: f64, },\n    Square(f64,),\n}",
+        "use std::fmt;",
+        "fn raw() -> &'static str {\n    r#\"fn b() {}\n}\n\"#\n}",
+    ];
+    let src = format!("{}\n", items.join("\n\n"));
+    fs::write(&path, &src).expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    let mut positions = items
+        .iter()
+        .map(|item| {
+            assert_eq!(
+                result.matches(item).count(),
+                1,
+                "item body changed:\n{item}\n\nin output:\n{result}"
+            );
+            result.find(item).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        result.len(),
+        src.len(),
+        "only item positions should change:\n{result}"
+    );
+    assert!(
+        result.ends_with("\n}\n") || result.ends_with(";\n"),
+        "last item was clipped:\n{result}"
+    );
+    let unsorted = positions.clone();
+    positions.sort_unstable();
+    assert_ne!(positions, unsorted, "items should have moved");
+}
+
+#[test]
+fn test_report_json() {
+    let dir = tmp_dir("report_json");
+    let path = dir.join("lib.rs");
+    let report = dir.join("report.json");
+    fs::write(&path, "fn run() {}\n\nuse std::fs;\n").expect("failed to write test file");
+
+    let output = run_refmt(&[
+        "--report-json",
+        report.to_str().unwrap(),
+        path.to_str().unwrap(),
+    ]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&report).expect("failed to read report");
+    assert_eq!(
+        result,
+        format!(
+            "\
+{{
+  \"summary\": {{ \"files\": 1, \"changed\": 1 }},
+  \"files\": [
+    {{
+      \"path\": \"{}\",
+      \"status\": \"changed\",
+      \"items\": [
+        {{ \"category\": \"use\", \"name\": null, \"original_line\": 3, \"new_line\": 1 }},
+        {{ \"category\": \"fn\", \"name\": \"run\", \"original_line\": 1, \"new_line\": 3 }}
+      ]
+    }}
+  ]
+}}
+",
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn test_rustfmt_skip_pins_item_position() {
+    let path = test_dir().join("rustfmt_skip.rs");
+    fs::write(
+        &path,
+        "\
+fn b() {}
+
+#[rustfmt::skip]
+const TABLE: [u8; 4] = [
+    1, 2,
+    3, 4,
+];
+
+use std::fs;
+
+fn a() {}
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(
+        result,
+        "\
+use std::fs;
+
+#[rustfmt::skip]
+const TABLE: [u8; 4] = [
+    1, 2,
+    3, 4,
+];
+
+fn a() {}
+
+fn b() {}
+"
+    );
+}
+
+#[test]
+fn test_shebang_kept_on_first_line() {
+    let input = "#!/usr/bin/env -S cargo +nightly -Zscript  \n#![allow(dead_code)]\n\npub fn run() {}\n\nuse std::fs;\n";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "#!/usr/bin/env -S cargo +nightly -Zscript  \n#![allow(dead_code)]\n\nuse std::fs;\n\npub fn run() {}\n"
+    );
+}
+
+#[test]
+fn test_skip_directive_absent() {
+    let path = test_dir().join("skip_absent.rs");
+    fs::write(
+        &path,
+        "\
+// reorder: keep going
+pub fn run() {}
+
+use std::fs;
+",
+    )
+    .expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert!(
+        result.starts_with("use std::fs;"),
+        "file without directive should be reordered: {result}"
+    );
+}
+
+#[test]
+fn test_skip_directive_leaves_file_untouched() {
+    let path = test_dir().join("skip_present.rs");
+    let src = "\
+// reorder:skip
+pub fn run() {}
+
+use std::fs;
+";
+    fs::write(&path, src).expect("failed to write test file");
+
+    let result = run_reorder(&path);
+
+    assert_eq!(result, src, "file with skip directive must not change");
+}
+
+#[test]
+fn test_sort_fields() {
+    let input = "\
+/// Settings.
+pub struct Settings {
+    /// Always written last.
+    pub verbose: bool, // -v
+    #[allow(dead_code)]
+    depth: HashMap<String, (u8, u8)>,
+    // Who runs it.
+    author: String
+}
+
+#[repr(C)]
+struct Header {
+    magic: u32,
+    length: u32,
+}
+
+enum Shape { Square(u8), Circle { radius: u8, center: u8 } }
+
+#[derive(PartialEq, PartialOrd)]
+enum Level { Low, High }
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--sort-fields"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+/// Settings.
+pub struct Settings {
+    // Who runs it.
+    author: String,
+    #[allow(dead_code)]
+    depth: HashMap<String, (u8, u8)>,
+    /// Always written last.
+    pub verbose: bool // -v
+}
+
+#[repr(C)]
+struct Header {
+    magic: u32,
+    length: u32,
+}
+
+enum Shape { Circle { radius: u8, center: u8 }, Square(u8) }
+
+#[derive(PartialEq, PartialOrd)]
+enum Level { Low, High }
+"
+    );
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), input);
+}
+
+#[test]
+fn test_sort_impl_items() {
+    let input = "\
+struct Counter {
+    count: u32,
+}
+
+impl Counter {
+    fn bump(&mut self) {
+        self.count += 1;
+    }
+
+    // Read the current value.
+    pub fn get(&self) -> u32 {
+        self.count
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    /// Starts at zero.
+    pub fn new() -> Self {
+        Self { count: 0, }
+    }
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--sort-impl-items"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+struct Counter {
+    count: u32,
+}
+
+impl Counter {
+    /// Starts at zero.
+    pub fn new() -> Self {
+        Self { count: 0, }
+    }
+
+    // Read the current value.
+    pub fn get(&self) -> u32 {
+        self.count
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    fn bump(&mut self) {
+        self.count += 1;
+    }
+}
+"
+    );
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        input,
+        "impl items keep their order by default"
+    );
+}
+
+#[test]
+fn test_sort_imports_by_path() {
+    let path = test_dir().join("sort_imports.rs");
+    fs::write(
+        &path,
+        "\
+use super::parent;
+pub(crate) use crate::util::helper;
+use std::io::Read;
+use self::local::Thing;
+use std::{fs, io};
+#[cfg(unix)]
+use std::collections::HashMap;
+use crate::config::Config;
+use std::io;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+extern crate zeta;
+extern crate alpha;
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--sort-imports", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+extern crate alpha;
+extern crate zeta;
+
+use std::{fs, io};
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+pub(crate) use crate::util::helper;
+use self::local::Thing;
+use super::parent;
+"
+    );
+}
+
+#[test]
+fn test_sort_within_categories() {
+    let input = "\
+pub fn zeta() {}
+
+fn alpha() {}
+
+macro_rules! noop {
+    () => {};
+}
+
+lazy_static! {}
+
+pub struct Zoo;
+
+pub struct Apple;
+
+impl Zoo {}
+
+impl Apple {}
+
+use std::io;
+use std::fs;
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--sort-within"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+use std::fs;
+use std::io;
+
+macro_rules! noop {
+    () => {};
+}
+
+pub struct Apple;
+
+pub struct Zoo;
+
+impl Apple {}
+
+impl Zoo {}
+
+fn alpha() {}
+
+pub fn zeta() {}
+
+lazy_static! {}
+"
+    );
+}
+
+#[test]
+fn test_sort_within_keeps_impls_of_a_type_in_order() {
+    let path = test_dir().join("sort_within_impls.rs");
+    fs::write(
+        &path,
+        "\
+impl fmt::Display for Zeta {}
+
+impl Alpha {}
+
+impl Zeta {}
+
+struct Zeta;
+
+struct Alpha;
+
+impl Debug for Remote {}
+
+impl Local for Other {}
+
+impl Remote {}
+",
+    )
+    .expect("failed to write test file");
+
+    let output = run_refmt(&["--sort-within", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(&path).expect("failed to read file");
+    assert_eq!(
+        result,
+        "\
+struct Alpha;
+
+struct Zeta;
+
+impl Alpha {}
+
+impl fmt::Display for Zeta {}
+
+impl Zeta {}
+
+impl Local for Other {}
+
+impl Debug for Remote {}
+
+impl Remote {}
+"
+    );
+}
+
+#[test]
+fn test_staged_processes_only_staged_files() {
+    let dir = tmp_dir("staged");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::write(dir.join("staged.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("unstaged.rs"), unsorted).expect("failed to write test file");
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(&dir)
+            .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap())
+            .stdout(Stdio::null())
+            .status()
+            .expect("failed to run git");
+        assert!(status.success(), "git {args:?} failed");
+    };
+    git(&["init", "-q"]);
+    git(&["add", "staged.rs"]);
+
+    let output = Command::new(cargo_bin())
+        .arg("--staged")
+        .current_dir(&dir)
+        .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap())
+        .output()
+        .expect("failed to run reorder");
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("staged.rs"), "use std::fs;\n\npub fn run() {}\n");
+    assert_eq!(read("unstaged.rs"), unsorted);
+
+    fs::remove_dir_all(dir.join(".git")).expect("failed to remove repository");
+    let output = Command::new(cargo_bin())
+        .arg("--staged")
+        .current_dir(&dir)
+        .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap())
+        .output()
+        .expect("failed to run reorder");
+
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("--staged requires a git repository"),
+        "got {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_stdin_honors_check_and_diff() {
+    let unsorted = "fn b() {}\nfn a() {}\n";
+    let sorted = "fn a() {}\n\nfn b() {}\n";
+
+    let output = run_refmt_stdin(&["--stdin", "--check"], unsorted);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "would reorder stdin\n"
+    );
+
+    let output = run_refmt_stdin(&["-", "--diff", "--color", "never"], unsorted);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "--- stdin\n+++ stdin\n@@ -1,2 +1,3 @@\n-fn b() {}\n fn a() {}\n+\n+fn b() {}\n"
+    );
+
+    for flag in ["--check", "--diff"] {
+        let output = run_refmt_stdin(&["--stdin", flag], sorted);
+        assert!(output.status.success(), "{flag} failed on sorted input");
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn test_stdin_parse_error_emits_nothing() {
+    let output = run_refmt_stdin(&["--stdin"], "fn broken( {");
+
+    assert!(!output.status.success(), "parse error should fail");
+    assert!(output.stdout.is_empty(), "no partial output on parse error");
+    assert!(!output.stderr.is_empty(), "parse error should be reported");
+}
+
+#[test]
+fn test_stdin_to_stdout() {
+    let input = "\
+pub fn run() {}
+
+use std::fs;
+";
+
+    for flag in ["-", "--stdin"] {
+        let output = run_refmt_stdin(&[flag], input);
+
+        assert!(
+            output.status.success(),
+            "stdin mode failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "\
+use std::fs;
+
+pub fn run() {}
+"
+        );
+    }
+}
+
+#[test]
+fn test_stdout_prints_without_writing() {
+    let dir = tmp_dir("stdout");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    let a = dir.join("a.rs");
+    let b = dir.join("b.rs");
+    fs::write(&a, unsorted).expect("failed to write test file");
+    fs::write(&b, sorted).expect("failed to write test file");
+
+    let output = run_refmt(&["--stdout", a.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), sorted);
+    let result = fs::read_to_string(&a).expect("failed to read file");
+    assert_eq!(result, unsorted, "--stdout must not rewrite the file");
+
+    let output = run_refmt(&["--stdout", a.to_str().unwrap(), b.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "// === {} ===\n{sorted}// === {} ===\n{sorted}",
+            a.display(),
+            b.display()
+        )
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_summary_counts_changed_files() {
+    let dir = tmp_dir("summary");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    fs::write(dir.join("a.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("b.rs"), sorted).expect("failed to write test file");
+    fs::write(dir.join("c.rs"), unsorted).expect("failed to write test file");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--check", dir_str]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("2 of 3 file(s) would be reordered"),
+        "stderr: {stderr}"
+    );
+
+    let output = run_refmt(&[dir_str]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("reordered 2 of 3 file(s)"),
+        "stderr: {stderr}"
+    );
+    assert!(stderr.contains("a.rs"), "stderr: {stderr}");
+    assert!(!stderr.contains("b.rs"), "stderr: {stderr}");
+    assert!(stderr.contains("c.rs"), "stderr: {stderr}");
+
+    let output = run_refmt(&[dir_str]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "reordered 0 of 3 file(s)\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_file_processed_once() {
+    let dir = tmp_dir("symlink");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    fs::write(dir.join("lib.rs"), unsorted).expect("failed to write test file");
+    std::os::unix::fs::symlink("lib.rs", dir.join("alias.rs")).expect("failed to create symlink");
+    let lib = dir.join("lib.rs");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--check", dir_str, lib.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("would reorder").count(),
+        1,
+        "stderr: {stderr}"
+    );
+
+    let output = run_refmt(&[dir_str]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&lib).expect("failed to read file"),
+        "use std::fs;\n\npub fn run() {}\n"
+    );
+    let alias = fs::symlink_metadata(dir.join("alias.rs")).expect("failed to inspect symlink");
+    assert!(alias.file_type().is_symlink(), "symlink was replaced");
+}
+
+#[test]
+fn test_tab_indented_non_ascii_items() {
+    let path = test_dir().join("tabs_non_ascii.rs");
     fs::write(
         &path,
         "\
-pub mod context;
+mod outer {
+\tpub fn été() -> &'static str {
+\t\t\"\t✓\"
+\t}
 
-pub mod ids;
-
-pub mod journal;
+\tconst Ω: u8 = 1;\t// ünïcode
 
-pub mod run;
+\tuse std::fs;
+}
 ",
     )
     .expect("failed to write test file");
@@ -324,78 +4088,202 @@ pub mod run;
     assert_eq!(
         result,
         "\
-pub mod context;
-pub mod ids;
-pub mod journal;
-pub mod run;
+mod outer {
+\tuse std::fs;
+
+\tconst Ω: u8 = 1;\t// ünïcode
+
+\tpub fn été() -> &'static str {
+\t\t\"\t✓\"
+\t}
+}
 "
     );
 }
 
 #[test]
-fn test_no_extra_blank_line_after_last_item() {
-    let path = test_dir().join("last_item.rs");
+fn test_target_dir_writes_mirror_tree() {
+    let dir = tmp_dir("target_dir");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n";
+    let sorted = "use std::fs;\n\npub fn run() {}\n";
+    fs::create_dir_all(dir.join("src/nested")).expect("failed to create test dir");
+    fs::write(dir.join("src/lib.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("src/nested/mod.rs"), unsorted).expect("failed to write test file");
+    fs::write(dir.join("src/sorted.rs"), sorted).expect("failed to write test file");
+
+    let output = Command::new(cargo_bin())
+        .args(["refmt", "--target-dir", "out", "src"])
+        .current_dir(&dir)
+        .output()
+        .expect("failed to run reorder");
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name: &str| fs::read_to_string(dir.join(name)).expect("failed to read file");
+    assert_eq!(read("out/src/lib.rs"), sorted);
+    assert_eq!(read("out/src/nested/mod.rs"), sorted);
+    assert_eq!(read("out/src/sorted.rs"), sorted);
+    assert_eq!(read("src/lib.rs"), unsorted);
+    assert_eq!(read("src/nested/mod.rs"), unsorted);
+
+    fs::write(dir.join("outside.rs"), unsorted).expect("failed to write test file");
+    let output = Command::new(cargo_bin())
+        .args(["refmt", "--target-dir", "out", "../outside.rs"])
+        .current_dir(dir.join("src"))
+        .output()
+        .expect("failed to run reorder");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("outside the current directory"),
+        "got {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_test_functions_grouped_with_tests() {
+    let path = test_dir().join("test_functions.rs");
     fs::write(
         &path,
         "\
-use uuid::Uuid;
+#[tokio::test]
+async fn serves_requests() {}
 
-pub type RunId = Uuid;
+#[test]
+fn parses_input() {}
 
-pub struct Foo {
-    bar: i32,
-}
+pub fn run() {}
+
+#[cfg(test)]
+mod tests {}
+
+fn helper() {}
 ",
     )
     .expect("failed to write test file");
 
     let result = run_reorder(&path);
 
-    assert!(
-        !result.ends_with("\n\n\n"),
-        "should not have extra blank line after last item"
+    assert_eq!(
+        result,
+        "\
+pub fn run() {}
+
+fn helper() {}
+
+#[cfg(test)]
+mod tests {}
+
+#[test]
+fn parses_input() {}
+
+#[tokio::test]
+async fn serves_requests() {}
+"
     );
 }
 
 #[test]
-fn test_preserve_no_trailing_newline() {
-    let path = test_dir().join("no_newline.rs");
-    fs::write(
-        &path,
-        "\
-use uuid::Uuid;
+fn test_trailing_block_comments_stay_on_item() {
+    let input = "\
+fn run() {} /* entry point */
+const LIMIT: u8 = 1; /* soft */ // hard at 2
+struct Last; /* the /* nested */ end */
+";
 
-pub type RunId = Uuid;",
-    )
-    .expect("failed to write test file");
+    let output = run_refmt_stdin(&["--stdin"], input);
 
-    let result = run_reorder(&path);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+const LIMIT: u8 = 1; /* soft */ // hard at 2
 
-    assert!(
-        !result.ends_with('\n'),
-        "should not add trailing newline to file without one"
+struct Last; /* the /* nested */ end */
+
+fn run() {} /* entry point */
+"
     );
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
 }
 
 #[test]
-fn test_preserve_trailing_newline() {
-    let path = test_dir().join("with_newline.rs");
+fn test_trailing_comments_stay_on_item() {
+    let path = test_dir().join("trailing_comments.rs");
     fs::write(
         &path,
         "\
-use uuid::Uuid;
+const MAX: u32 = 10; // hard limit
+// Minimum accepted value.
+const MIN: u32 = 1;
 
-pub type RunId = Uuid;
+type Id = u64; // opaque
+
+use std::fs; // for reading
+use crate::Thing;
 ",
     )
     .expect("failed to write test file");
 
     let result = run_reorder(&path);
 
-    assert!(result.ends_with('\n'), "should preserve trailing newline");
+    assert_eq!(
+        result,
+        "\
+use std::fs; // for reading
+
+use crate::Thing;
+
+type Id = u64; // opaque
+
+const MAX: u32 = 10; // hard limit
+// Minimum accepted value.
+const MIN: u32 = 1;
+"
+    );
+}
+
+#[test]
+fn test_trim_blank_lines() {
+    let dir = tmp_dir("trim_blank_lines");
+    let header_only = dir.join("header_only.rs");
+    let items = dir.join("items.rs");
+    fs::write(&header_only, "\n\n#![allow(dead_code)]\n//! Docs.\n\n\n\n")
+        .expect("failed to write test file");
+    fs::write(&items, "\n\n\nfn b() {}\n\nfn a() {}\n\n\n").expect("failed to write test file");
+
+    let output = run_refmt(&["--check", dir.to_str().unwrap()]);
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "files with items are trimmed anyway"
+    );
+    let output = run_refmt(&["--trim-blank-lines", dir.to_str().unwrap()]);
+
     assert!(
-        !result.ends_with("\n\n"),
-        "should not add extra trailing newline"
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(&header_only).unwrap(),
+        "#![allow(dead_code)]\n//! Docs.\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&items).unwrap(),
+        "fn a() {}\n\nfn b() {}\n"
+    );
+
+    fs::write(&header_only, "\n\n//! Docs.\n\n\n").expect("failed to write test file");
+    let output = run_refmt(&[header_only.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&header_only).unwrap(),
+        "\n\n//! Docs.\n\n\n",
+        "files are left alone without the flag"
     );
 }
 
@@ -433,3 +4321,240 @@ pub type ValidatorId = &'static str;
 "
     );
 }
+
+#[test]
+fn test_types_with_consts() {
+    let input = "\
+fn run() {}
+
+const LIMIT: u8 = 4;
+pub type Id = u32;
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+type Bytes = Vec<u8>;
+";
+
+    let output = run_refmt_stdin(&["--stdin", "--types-with-consts"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+type Bytes = Vec<u8>;
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+pub type Id = u32;
+const LIMIT: u8 = 4;
+
+fn run() {}
+"
+    );
+
+    let config = tmp_dir("types_after_consts").join("reorder.toml");
+    fs::write(&config, "category_order = [\"const\", \"type\"]\n").expect("failed to write config");
+
+    let output = run_refmt_stdin(&["--stdin", "--config", config.to_str().unwrap()], input);
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+const LIMIT: u8 = 4;
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub type Id = u32;
+type Bytes = Vec<u8>;
+
+fn run() {}
+"
+    );
+}
+
+#[test]
+fn test_unions_have_own_section() {
+    let input = "\
+#[repr(C)]
+pub union Raw {
+    int: u32,
+    float: f32,
+}
+
+pub struct Wrapper(Raw);
+
+union Bits {
+    byte: u8,
+}
+
+enum Kind {
+    A,
+}
+";
+
+    let output = run_refmt_stdin(&["--stdin"], input);
+
+    assert!(
+        output.status.success(),
+        "reorder failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+pub struct Wrapper(Raw);
+
+enum Kind {
+    A,
+}
+
+#[repr(C)]
+pub union Raw {
+    int: u32,
+    float: f32,
+}
+
+union Bits {
+    byte: u8,
+}
+"
+    );
+
+    let config = tmp_dir("union_order").join("reorder.toml");
+    fs::write(&config, "category_order = [\"union\", \"typedef\"]\n")
+        .expect("failed to write config");
+
+    let output = run_refmt_stdin(&["--stdin", "--config", config.to_str().unwrap()], input);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("#[repr(C)]\npub union Raw {"),
+        "stdout: {stdout}"
+    );
+    assert!(stdout.find("union Bits").unwrap() < stdout.find("pub struct Wrapper").unwrap());
+}
+
+#[test]
+fn test_unparseable_files_are_skipped_unless_strict() {
+    let dir = tmp_dir("unparseable");
+    let broken = "fn broken( {\n";
+    fs::write(dir.join("a_broken.rs"), broken).unwrap();
+    fs::write(dir.join("b_valid.rs"), "fn b() {}\n\nfn a() {}\n").unwrap();
+
+    let output = run_refmt(&[dir.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: skipping") && stderr.contains("a_broken.rs"),
+        "got {stderr}"
+    );
+    assert!(
+        stderr.contains("reordered 1 of 2 file(s)\nskipped 1 file(s) that do not parse\n"),
+        "got {stderr}"
+    );
+    assert_eq!(fs::read_to_string(dir.join("a_broken.rs")).unwrap(), broken);
+    assert_eq!(
+        fs::read_to_string(dir.join("b_valid.rs")).unwrap(),
+        "fn a() {}\n\nfn b() {}\n"
+    );
+
+    let output = run_refmt(&["--quiet", dir.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = run_refmt(&["--fail-on-parse-error", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: refmt"), "got {stderr}");
+    assert!(stderr.contains("a_broken.rs"), "got {stderr}");
+}
+
+#[test]
+fn test_verbosity_levels() {
+    let dir = tmp_dir("verbosity");
+    fs::write(dir.join("a.rs"), "pub fn run() {}\n\nuse std::fs;\n")
+        .expect("failed to write test file");
+    fs::write(dir.join("b.rs"), "use std::fs;\n\npub fn run() {}\n")
+        .expect("failed to write test file");
+    let dir_str = dir.to_str().unwrap();
+
+    let output = run_refmt(&["--check", dir_str]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would reorder"), "stderr: {stderr}");
+    assert!(!stderr.contains("b.rs"), "stderr: {stderr}");
+
+    let output = run_refmt(&["--check", "-v", dir_str]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unchanged"), "stderr: {stderr}");
+    assert!(stderr.contains("b.rs"), "stderr: {stderr}");
+    assert!(!stderr.contains("scanned"), "stderr: {stderr}");
+
+    let output = run_refmt(&["--check", "-vv", dir_str]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("scanned").count(), 2, "stderr: {stderr}");
+    assert!(
+        stderr.contains("  fn run at line 1 -> line 3"),
+        "stderr: {stderr}"
+    );
+    assert!(
+        stderr.contains("  use (unnamed) at line 3 -> line 1"),
+        "stderr: {stderr}"
+    );
+
+    let output = run_refmt(&["-q", "-v", dir_str]);
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_verify_accepts_idempotent_output() {
+    let dir = tmp_dir("verify");
+    let path = dir.join("lib.rs");
+    let unsorted = "pub fn run() {}\n\nuse std::fs;\n\nstruct Config;\n\nimpl Config {}\n";
+    fs::write(&path, unsorted).expect("failed to write test file");
+
+    let output = run_refmt(&["--verify", "--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&path).unwrap(), unsorted);
+
+    let output = run_refmt(&["--verify", "--impls-with-types", path.to_str().unwrap()]);
+
+    assert!(
+        output.status.success(),
+        "verify failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "use std::fs;\n\nstruct Config;\n\nimpl Config {}\n\npub fn run() {}\n"
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_relative_and_absolute_paths_dedupe() {
+    let dir = tmp_dir("windows_paths");
+    let path = dir.join("lib.rs");
+    fs::write(&path, "fn b() {}\n\nfn a() {}\n").unwrap();
+    let canonical = fs::canonicalize(&path).unwrap();
+
+    let output = Command::new(cargo_bin())
+        .args([
+            "--check",
+            "lib.rs",
+            path.to_str().unwrap(),
+            canonical.to_str().unwrap(),
+        ])
+        .current_dir(&dir)
+        .output()
+        .expect("failed to run refmt");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1 of 1 file(s) would be reordered"),
+        "got {stderr}"
+    );
+}
@@ -0,0 +1,5 @@
+﻿//! Crate docs.
+
+use std::fs;
+
+pub fn run() {}
@@ -0,0 +1,12 @@
+const LIMIT: usize = 4;
+
+pub fn run() {}
+
+#[cfg(test)]
+const FIXTURE: &str = "input";
+
+#[cfg(test)]
+mod tests {}
+
+#[cfg(test)]
+fn helper() {}
@@ -0,0 +1,3 @@
+fn a() {}
+
+fn b() {}
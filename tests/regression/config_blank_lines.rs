@@ -0,0 +1,9 @@
+use std::fs;
+use std::io;
+
+
+struct Foo;
+
+
+fn a() {}
+fn b() {}
@@ -0,0 +1,7 @@
+pub fn run() {}
+
+impl Foo {}
+
+use std::fs;
+
+pub struct Foo;
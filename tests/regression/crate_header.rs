@@ -0,0 +1,23 @@
+//! Crate docs.
+//!
+//! More docs.
+
+#![deny(missing_docs)] // kept strict
+//! Docs after an attribute.
+#![allow(dead_code)]
+
+use std::fs;
+
+mod inner {
+    //! Inner docs.
+
+    // Why inner code is allowed to be dead.
+    #![allow(dead_code)]
+
+    fn a() {}
+
+    fn b() {}
+}
+
+/// Runs.
+pub fn run() {}
@@ -0,0 +1,6 @@
+use std::fs;
+use std::io;
+
+pub fn run() {
+    let x = 1;
+}
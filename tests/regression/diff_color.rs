@@ -0,0 +1,3 @@
+use std::fs;
+
+pub fn run() {}
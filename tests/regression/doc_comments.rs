@@ -0,0 +1,19 @@
+use std::fs;
+
+/**
+ * Block docs for Config.
+ */
+pub struct Config;
+
+/** Inline block doc. */
+#[derive(Debug)]
+pub enum Mode {
+    A,
+}
+
+/// Runs the thing.
+///
+/// More detail.
+#[inline]
+/// Continued after an attribute.
+pub fn run() {}
@@ -0,0 +1,9 @@
+use std::fs;
+
+mod a;
+
+#[macro_use]
+extern crate log;
+extern crate serde;
+
+fn run() {}
@@ -0,0 +1,20 @@
+fn run() {}
+pub use a::A;
+use std::fs;
+macro_rules! m { () => {} }
+mod declared;
+mod inline {}
+extern crate alloc;
+type Alias = u8;
+const C: u8 = 0;
+trait T {}
+struct S;
+union U { a: u8 }
+impl S {}
+extern "C" {}
+#[cfg(test)]
+mod tests {}
+#[test]
+fn check() {}
+#[cfg(test)]
+fn helper() {}
@@ -0,0 +1,12 @@
+extern crate serde;
+
+use alloc::vec::Vec;
+use core::fmt;
+use std::fs;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::config::Config;
+use self::local::Thing;
+use super::parent;
@@ -0,0 +1,11 @@
+use std::fs;
+
+// Shared buffer size.
+const SIZE: usize = 4;
+
+// Holds the state.
+pub struct State;
+
+// SAFETY: invariants below
+// hold for every caller.
+pub fn run() {}
@@ -0,0 +1,15 @@
+use std::fs;
+
+macro_rules! square {
+    ($x:expr) => {
+        $x * $x
+    };
+}
+
+lazy_static! {
+    static ref NAME: String = String::new();
+}
+
+pub fn run() -> u32 {
+    square!(3)
+}
@@ -0,0 +1,8 @@
+mod a;
+pub mod c;
+
+mod b {
+    pub fn run() {}
+}
+
+mod d {}
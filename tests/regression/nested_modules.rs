@@ -0,0 +1,26 @@
+use std::fmt;
+
+/// Outer docs.
+pub mod outer {
+    #![allow(dead_code)]
+
+    use std::io;
+
+    // Inner module.
+    mod inner {
+        use std::fs;
+
+        const LIMIT: u8 = 1;
+
+        fn helper() {}
+    }
+
+    pub fn run() {}
+}
+
+#[cfg(test)]
+mod tests {
+    fn b() {}
+
+    use super::*;
+}
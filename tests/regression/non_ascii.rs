@@ -0,0 +1,7 @@
+use std::fs;
+
+/// 配置结构体
+pub struct Größe { wert: u8 } // 🦀
+
+/// Renvoie la valeur par défaut 🎉.
+pub fn défaut() -> u8 { 0 }
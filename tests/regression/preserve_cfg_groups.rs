@@ -0,0 +1,17 @@
+use std::fs;
+
+const SHARED: u8 = 0;
+
+struct Shared;
+
+fn alpha() {}
+
+#[cfg(feature = "b")]
+fn zeta() {}
+#[cfg(feature = "b")]
+const LIMIT: u8 = 2;
+
+#[cfg(feature = "a")]
+fn zeta() {}
+#[cfg(feature = "a")]
+const LIMIT: u8 = 1;
@@ -0,0 +1,11 @@
+pub use std::io::Error;
+
+pub use crate::config::Config;
+
+use std::fs;
+
+use anyhow::Result;
+
+pub(crate) use crate::util::helper;
+
+pub fn run() {}
@@ -0,0 +1,11 @@
+use std::fs;
+
+#[rustfmt::skip]
+const TABLE: [u8; 4] = [
+    1, 2,
+    3, 4,
+];
+
+fn a() {}
+
+fn b() {}
@@ -0,0 +1,4 @@
+use std::fs;
+
+// reorder: keep going
+pub fn run() {}
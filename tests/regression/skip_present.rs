@@ -0,0 +1,4 @@
+// reorder:skip
+pub fn run() {}
+
+use std::fs;
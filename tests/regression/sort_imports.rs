@@ -0,0 +1,16 @@
+extern crate alpha;
+extern crate zeta;
+
+use std::{fs, io};
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+pub(crate) use crate::util::helper;
+use self::local::Thing;
+use super::parent;
@@ -0,0 +1,15 @@
+struct Alpha;
+
+struct Zeta;
+
+impl Alpha {}
+
+impl fmt::Display for Zeta {}
+
+impl Zeta {}
+
+impl Local for Other {}
+
+impl Debug for Remote {}
+
+impl Remote {}
@@ -0,0 +1,9 @@
+mod outer {
+	use std::fs;
+
+	const Ω: u8 = 1;	// ünïcode
+
+	pub fn été() -> &'static str {
+		"	✓"
+	}
+}
@@ -0,0 +1,12 @@
+pub fn run() {}
+
+fn helper() {}
+
+#[cfg(test)]
+mod tests {}
+
+#[test]
+fn parses_input() {}
+
+#[tokio::test]
+async fn serves_requests() {}
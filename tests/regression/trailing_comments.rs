@@ -0,0 +1,9 @@
+use std::fs; // for reading
+
+use crate::Thing;
+
+type Id = u64; // opaque
+
+const MAX: u32 = 10; // hard limit
+// Minimum accepted value.
+const MIN: u32 = 1;
@@ -0,0 +1,7 @@
+impl Display for Widget {}
+
+impl Parser for syn::File {}
+
+struct Widget;
+
+impl Widgt {}